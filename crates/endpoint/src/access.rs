@@ -0,0 +1,216 @@
+use std::{collections::HashMap, sync::Arc};
+
+use iroh::EndpointId;
+use iroh_blobs::{
+    Hash,
+    api::Store,
+    provider::events::{
+        AbortReason, ConnectMode, EventMask, EventResult, EventSender, ObserveMode,
+        ProviderMessage, RequestMode, ThrottleMode,
+    },
+};
+use n0_future::task::AbortOnDropHandle;
+use person_protocol::{PersonProtocol, privacy::Audience};
+
+use crate::storage::BlobOwner;
+
+const EVENT_CAPACITY: usize = 32;
+const SHARED_OWNERS: [BlobOwner; 3] = [
+    BlobOwner::Share,
+    BlobOwner::ChatAttachment,
+    BlobOwner::GroupHistory,
+];
+
+#[derive(Clone)]
+pub(crate) struct BlobAccess {
+    person_protocol: PersonProtocol,
+    store: Store,
+}
+impl BlobAccess {
+    pub fn spawn(
+        person_protocol: PersonProtocol,
+        store: Store,
+    ) -> (EventSender, Arc<AbortOnDropHandle<()>>) {
+        let (sender, mut receiver) = EventSender::channel(
+            EVENT_CAPACITY,
+            EventMask {
+                connected: ConnectMode::Intercept,
+                get: RequestMode::Intercept,
+                get_many: RequestMode::Intercept,
+                push: RequestMode::Disabled,
+                observe: ObserveMode::Intercept,
+                throttle: ThrottleMode::None,
+            },
+        );
+        let access = Self {
+            person_protocol,
+            store,
+        };
+        let task = n0_future::task::spawn(async move {
+            let mut peers = HashMap::new();
+            while let Some(message) = receiver.recv().await {
+                match message {
+                    ProviderMessage::ClientConnected(message) => {
+                        let result = match message.inner.endpoint_id {
+                            Some(id) if access.person_protocol.policy().is_allowed(&id) => {
+                                peers.insert(message.inner.connection_id, id);
+                                Ok(())
+                            }
+                            _ => Err(AbortReason::Permission),
+                        };
+                        message.tx.send(result).await.ok();
+                    }
+                    ProviderMessage::ConnectionClosed(message) => {
+                        peers.remove(&message.inner.connection_id);
+                    }
+                    ProviderMessage::GetRequestReceived(message) => {
+                        let peer = peers.get(&message.inner.connection_id);
+                        let result = access.check(peer, &[message.inner.request.hash]).await;
+                        message.tx.send(result).await.ok();
+                    }
+                    ProviderMessage::GetManyRequestReceived(message) => {
+                        let peer = peers.get(&message.inner.connection_id);
+                        let result = access.check(peer, &message.inner.request.hashes).await;
+                        message.tx.send(result).await.ok();
+                    }
+                    ProviderMessage::ObserveRequestReceived(message) => {
+                        let peer = peers.get(&message.inner.connection_id);
+                        let result = access.check(peer, &[message.inner.request.hash]).await;
+                        message.tx.send(result).await.ok();
+                    }
+                    _ => {}
+                }
+            }
+        });
+        (sender, Arc::new(AbortOnDropHandle::new(task)))
+    }
+    async fn check(&self, peer: Option<&EndpointId>, hashes: &[Hash]) -> EventResult {
+        let Some(peer) = peer else {
+            return Err(AbortReason::Permission);
+        };
+        for hash in hashes {
+            if !self.is_allowed(peer, *hash).await {
+                return Err(AbortReason::Permission);
+            }
+        }
+        Ok(())
+    }
+    async fn is_allowed(&self, peer: &EndpointId, hash: Hash) -> bool {
+        let policy = self.person_protocol.policy();
+        let is_friend = policy.is_friend(peer);
+        let (person, _) = self.person_protocol.profile();
+        if person
+            .avatar
+            .is_some_and(|avatar| Hash::from_bytes(avatar.hash) == hash)
+        {
+            return policy.privacy().avatar.is_visible(Audience::of(is_friend));
+        }
+        if !is_friend {
+            return false;
+        }
+        for owner in SHARED_OWNERS {
+            match self.store.tags().get(owner.tag(hash)).await {
+                Ok(Some(_)) => return true,
+                Ok(None) => {}
+                Err(err) => {
+                    log::warn!("查询数据标签失败：{}", err);
+                    return false;
+                }
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use eyre::Result;
+    use iroh::SecretKey;
+    use iroh_blobs::protocol::GetRequest;
+
+    use super::*;
+    use crate::{
+        AddressLookupOption, Endpoint, LocalPerson, Privacy, RelayOption, StoreOption, Visibility,
+        policy::PolicyOption,
+    };
+
+    async fn endpoint(avatar: Option<Vec<u8>>) -> Endpoint {
+        Endpoint::builder(
+            SecretKey::generate(&mut rand::rng()).to_bytes().to_vec(),
+            LocalPerson {
+                name: "测试".to_string(),
+                avatar,
+                bio: String::new(),
+            },
+        )
+        .relay(RelayOption::Disabled)
+        .address_lookup(AddressLookupOption::disabled())
+        .store(StoreOption::Memory)
+        .policy(PolicyOption::Memory)
+        .bind()
+        .await
+        .unwrap()
+    }
+
+    async fn get(from: &Endpoint, to: &Endpoint, hash: Hash) -> Result<()> {
+        let connection = from
+            .router
+            .endpoint()
+            .connect(to.router.endpoint().addr(), iroh_blobs::ALPN)
+            .await?;
+        from.storage
+            .store()
+            .remote()
+            .execute_get(connection, GetRequest::blob(hash))
+            .complete()
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refuses_shared_blobs_to_non_friends() {
+        let alice = endpoint(None).await;
+        let bob = endpoint(None).await;
+        let shared = alice.share_bytes(b"shared".to_vec()).await.unwrap();
+        let hash = shared.hash.parse().unwrap();
+        let private = alice
+            .storage
+            .store()
+            .add_bytes(b"private".to_vec())
+            .await
+            .unwrap()
+            .hash;
+
+        assert!(get(&bob, &alice, hash).await.is_err());
+        alice.add_friend(bob.id()).await.unwrap();
+        get(&bob, &alice, hash).await.unwrap();
+        assert!(get(&bob, &alice, private).await.is_err());
+
+        alice.close().await.unwrap();
+        bob.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn serves_avatar_by_privacy() {
+        let alice = endpoint(Some(b"avatar".to_vec())).await;
+        let bob = endpoint(None).await;
+        let carol = endpoint(None).await;
+        let hash = Hash::new(b"avatar");
+
+        get(&carol, &alice, hash).await.unwrap();
+        alice
+            .set_privacy(Privacy {
+                avatar: Visibility::Friends,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(get(&bob, &alice, hash).await.is_err());
+        alice.add_friend(bob.id()).await.unwrap();
+        get(&bob, &alice, hash).await.unwrap();
+
+        alice.close().await.unwrap();
+        bob.close().await.unwrap();
+        carol.close().await.unwrap();
+    }
+}
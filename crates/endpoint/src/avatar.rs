@@ -0,0 +1,85 @@
+use std::pin::pin;
+
+use eyre::{Result, bail};
+use futures::StreamExt;
+use iroh::EndpointId;
+use iroh_blobs::{
+    Hash,
+    api::{Store, remote::GetProgressItem},
+};
+use person_protocol::{AVATAR_MAX_SIZE, Avatar, Person};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Storage;
+
+const AVATAR_TAG: &str = "avatar";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LocalPerson {
+    pub name: String,
+    pub avatar: Option<Vec<u8>>,
+    pub bio: String,
+}
+impl LocalPerson {
+    pub(crate) async fn import(self, store: &Store) -> Result<Person> {
+        let avatar = match self.avatar {
+            Some(avatar) => {
+                let size = avatar.len() as u64;
+                if size > AVATAR_MAX_SIZE {
+                    bail!("头像文件过大");
+                }
+                let hash_and_format = store.add_bytes(avatar).with_named_tag(AVATAR_TAG).await?;
+                Some(Avatar {
+                    hash: *hash_and_format.hash.as_bytes(),
+                    size,
+                })
+            }
+            None => {
+                store.tags().delete(AVATAR_TAG).await?;
+                None
+            }
+        };
+        Ok(Person {
+            name: self.name,
+            avatar,
+            bio: self.bio,
+        })
+    }
+}
+
+pub(crate) async fn fetch(
+    endpoint: &iroh::Endpoint,
+    storage: &Storage,
+    remote_id: EndpointId,
+    hash: Hash,
+    size: u64,
+) -> Result<Vec<u8>> {
+    if size > AVATAR_MAX_SIZE {
+        bail!("头像文件过大");
+    }
+    let store = storage.store();
+    if !store.has(hash).await? {
+        let local = store.remote().local(hash).await?;
+        let connection = endpoint.connect(remote_id, iroh_blobs::ALPN).await?;
+        let mut progress = pin!(
+            store
+                .remote()
+                .execute_get(connection, local.missing())
+                .stream()
+        );
+        loop {
+            match progress.next().await {
+                Some(GetProgressItem::Progress(bytes)) => {
+                    if local.local_bytes() + bytes > size {
+                        bail!("头像大小超出声明");
+                    }
+                }
+                Some(GetProgressItem::Done(_)) => break,
+                Some(GetProgressItem::Error(err)) => return Err(err.into()),
+                None => bail!("下载意外中断"),
+            }
+        }
+    }
+    storage.cache(hash.into()).await?;
+    Ok(store.get_bytes(hash).await?.to_vec())
+}
@@ -0,0 +1,296 @@
+use std::{path::PathBuf, sync::Arc};
+
+use eyre::Result;
+use iroh::{RelayConfig, RelayMap, RelayMode, SecretKey, address_lookup::PkarrPublisher};
+use iroh_blobs::api::Store;
+use iroh_relay::RelayQuicConfig;
+use person_protocol::{
+    Timeouts,
+    limit::Limits,
+    policy::{Policy, PolicyStore},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Endpoint,
+    avatar::LocalPerson,
+    policy::PolicyOption,
+    storage::{Storage, gc_config},
+};
+
+const DEFAULT_RELAY_URL: &str = "https://dev.zhangxichang.com:10281";
+const DEFAULT_RELAY_QUIC_PORT: u16 = 10282;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RelayServer {
+    pub url: String,
+    pub quic_port: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub enum RelayOption {
+    #[default]
+    Default,
+    Custom(Vec<RelayServer>),
+    Disabled,
+}
+impl RelayOption {
+    fn into_relay_mode(self) -> Result<RelayMode> {
+        Ok(match self {
+            RelayOption::Default => {
+                let relay_map = RelayMode::Default.relay_map();
+                relay_map.extend(&Self::relay_map(vec![RelayServer {
+                    url: DEFAULT_RELAY_URL.to_string(),
+                    quic_port: Some(DEFAULT_RELAY_QUIC_PORT),
+                }])?);
+                RelayMode::Custom(relay_map)
+            }
+            RelayOption::Custom(relay_servers) => {
+                RelayMode::Custom(Self::relay_map(relay_servers)?)
+            }
+            RelayOption::Disabled => RelayMode::Disabled,
+        })
+    }
+    fn relay_map(relay_servers: Vec<RelayServer>) -> Result<RelayMap> {
+        relay_servers
+            .into_iter()
+            .map(|v| {
+                eyre::Ok(RelayConfig {
+                    url: v.url.parse()?,
+                    quic: v.quic_port.map(|port| RelayQuicConfig { port }),
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AddressLookupOption {
+    pub pkarr: bool,
+    pub dns: bool,
+    pub mdns: bool,
+    pub dht: bool,
+}
+impl Default for AddressLookupOption {
+    fn default() -> Self {
+        Self {
+            pkarr: true,
+            dns: true,
+            mdns: true,
+            dht: true,
+        }
+    }
+}
+impl AddressLookupOption {
+    pub fn disabled() -> Self {
+        Self {
+            pkarr: false,
+            dns: false,
+            mdns: false,
+            dht: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum StoreOption {
+    Memory,
+    Fs(PathBuf),
+}
+impl Default for StoreOption {
+    fn default() -> Self {
+        #[cfg(not(target_family = "wasm"))]
+        return StoreOption::Fs("store".into());
+        #[cfg(target_family = "wasm")]
+        return StoreOption::Memory;
+    }
+}
+impl StoreOption {
+    pub async fn load(self) -> Result<Store> {
+        Ok(match self {
+            StoreOption::Memory => {
+                iroh_blobs::store::mem::MemStore::new_with_opts(iroh_blobs::store::mem::Options {
+                    gc_config: Some(gc_config()),
+                })
+                .into()
+            }
+            #[cfg(not(target_family = "wasm"))]
+            StoreOption::Fs(path) => {
+                use iroh_blobs::store::fs::{FsStore, options::Options};
+
+                FsStore::load_with_opts(
+                    path.join("blobs.db"),
+                    Options {
+                        gc: Some(gc_config()),
+                        ..Options::new(&path)
+                    },
+                )
+                .await
+                .map_err(|err| eyre::eyre!(err))?
+                .into()
+            }
+            #[cfg(target_family = "wasm")]
+            StoreOption::Fs(_) => eyre::bail!("当前平台不支持文件存储"),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EndpointConfig {
+    pub relay: RelayOption,
+    pub address_lookup: AddressLookupOption,
+    pub store: StoreOption,
+    pub quota: Option<u64>,
+    pub bind_port_v4: Option<u16>,
+    pub bind_port_v6: Option<u16>,
+    pub timeouts: Timeouts,
+    pub limits: Limits,
+    pub policy: PolicyOption,
+}
+
+pub struct EndpointBuilder {
+    secret_key: Vec<u8>,
+    person: LocalPerson,
+    config: EndpointConfig,
+    policy_store: Option<Arc<dyn PolicyStore>>,
+    blob_store: Option<Store>,
+}
+impl EndpointBuilder {
+    pub fn new(secret_key: Vec<u8>, person: LocalPerson) -> Self {
+        Self {
+            secret_key,
+            person,
+            config: Default::default(),
+            policy_store: None,
+            blob_store: None,
+        }
+    }
+    pub fn config(mut self, config: EndpointConfig) -> Self {
+        self.config = config;
+        self
+    }
+    pub fn relay(mut self, relay: RelayOption) -> Self {
+        self.config.relay = relay;
+        self
+    }
+    pub fn address_lookup(mut self, address_lookup: AddressLookupOption) -> Self {
+        self.config.address_lookup = address_lookup;
+        self
+    }
+    pub fn store(mut self, store: StoreOption) -> Self {
+        self.config.store = store;
+        self
+    }
+    pub fn quota(mut self, quota: u64) -> Self {
+        self.config.quota = Some(quota);
+        self
+    }
+    pub fn bind_port_v4(mut self, port: u16) -> Self {
+        self.config.bind_port_v4 = Some(port);
+        self
+    }
+    pub fn bind_port_v6(mut self, port: u16) -> Self {
+        self.config.bind_port_v6 = Some(port);
+        self
+    }
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.config.timeouts = timeouts;
+        self
+    }
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+    pub fn policy(mut self, policy: PolicyOption) -> Self {
+        self.config.policy = policy;
+        self
+    }
+    pub fn policy_store(mut self, policy_store: impl PolicyStore + 'static) -> Self {
+        self.policy_store = Some(Arc::new(policy_store));
+        self
+    }
+    pub fn blob_store(mut self, blob_store: Store) -> Self {
+        self.blob_store = Some(blob_store);
+        self
+    }
+    pub async fn bind(self) -> Result<Endpoint> {
+        let EndpointConfig {
+            relay,
+            address_lookup,
+            store,
+            quota,
+            bind_port_v4,
+            bind_port_v6,
+            timeouts,
+            limits,
+            policy,
+        } = self.config;
+        let policy = Policy::new(match self.policy_store {
+            Some(policy_store) => policy_store,
+            None => policy.into_store(&store)?,
+        })?;
+        let store = match self.blob_store {
+            Some(blob_store) => blob_store,
+            None => store.load().await?,
+        };
+        let avatar = self.person.avatar.clone();
+        let person = self.person.import(&store).await?;
+        let mut endpoint_builder = iroh::Endpoint::empty_builder(relay.into_relay_mode()?);
+        if address_lookup.pkarr {
+            endpoint_builder = endpoint_builder.address_lookup(PkarrPublisher::n0_dns());
+        }
+        #[cfg(not(target_family = "wasm"))]
+        {
+            use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+            use iroh::{
+                address_lookup::{DhtAddressLookup, DnsAddressLookup, MdnsAddressLookup},
+                endpoint::BindOpts,
+            };
+
+            if address_lookup.dns {
+                endpoint_builder = endpoint_builder.address_lookup(DnsAddressLookup::n0_dns());
+            }
+            if address_lookup.mdns {
+                endpoint_builder = endpoint_builder.address_lookup(MdnsAddressLookup::builder());
+            }
+            if address_lookup.dht {
+                endpoint_builder = endpoint_builder.address_lookup(DhtAddressLookup::builder());
+            }
+            if bind_port_v4.is_some() || bind_port_v6.is_some() {
+                endpoint_builder = endpoint_builder
+                    .clear_ip_transports()
+                    .bind_addr(SocketAddr::new(
+                        Ipv4Addr::UNSPECIFIED.into(),
+                        bind_port_v4.unwrap_or_default(),
+                    ))?
+                    .bind_addr_with_opts(
+                        SocketAddr::new(
+                            Ipv6Addr::UNSPECIFIED.into(),
+                            bind_port_v6.unwrap_or_default(),
+                        ),
+                        BindOpts::default().set_is_required(false),
+                    )?;
+            }
+        }
+        #[cfg(target_family = "wasm")]
+        let _ = (bind_port_v4, bind_port_v6);
+        let endpoint = endpoint_builder
+            .secret_key(SecretKey::from_bytes(
+                self.secret_key.as_slice().try_into()?,
+            ))
+            .bind()
+            .await?;
+        let endpoint = Endpoint::spawn(
+            endpoint,
+            person,
+            Storage::new(store, policy.quota().or(quota)),
+            timeouts,
+            limits,
+            policy,
+        )?;
+        endpoint.person_protocol.avatars().set_local(avatar);
+        Ok(endpoint)
+    }
+}
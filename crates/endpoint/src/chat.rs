@@ -0,0 +1,78 @@
+use std::sync::Arc;
+
+use eyre::{Result, bail};
+use futures::lock::Mutex;
+use iroh::endpoint::{
+    Connection, ConnectionError, ReadError, ReadExactError, RecvStream, SendStream, VarInt,
+};
+use utils::option_ext::OptionGet;
+
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Clone)]
+pub struct Chat {
+    connection: Connection,
+    send_stream: Arc<Mutex<Option<SendStream>>>,
+    recv_stream: Arc<Mutex<Option<RecvStream>>>,
+}
+impl Chat {
+    pub fn new(connection: Connection) -> Self {
+        Self {
+            connection,
+            send_stream: Default::default(),
+            recv_stream: Default::default(),
+        }
+    }
+    pub async fn send(&self, payload: Vec<u8>) -> Result<()> {
+        if payload.len() > MAX_MESSAGE_SIZE {
+            bail!("消息长度超出限制");
+        }
+        let mut send_stream = self.send_stream.lock().await;
+        if send_stream.is_none() {
+            send_stream.replace(self.connection.open_uni().await?);
+        }
+        let send_stream = send_stream.as_mut().get()?;
+        send_stream
+            .write_all(&(payload.len() as u32).to_be_bytes())
+            .await?;
+        send_stream.write_all(&payload).await?;
+        Ok(())
+    }
+    pub async fn next(&self) -> Result<Option<Vec<u8>>> {
+        let mut recv_stream = self.recv_stream.lock().await;
+        if recv_stream.is_none() {
+            match self.connection.accept_uni().await {
+                Ok(v) => recv_stream.replace(v),
+                Err(ConnectionError::ApplicationClosed(_) | ConnectionError::LocallyClosed) => {
+                    return Ok(None);
+                }
+                Err(err) => return Err(err.into()),
+            };
+        }
+        let recv_stream = recv_stream.as_mut().get()?;
+        let mut length = [0; 4];
+        match recv_stream.read_exact(&mut length).await {
+            Ok(()) => (),
+            Err(ReadExactError::FinishedEarly(0))
+            | Err(ReadExactError::ReadError(ReadError::ConnectionLost(
+                ConnectionError::ApplicationClosed(_) | ConnectionError::LocallyClosed,
+            ))) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let length = u32::from_be_bytes(length) as usize;
+        if length > MAX_MESSAGE_SIZE {
+            bail!("消息长度超出限制");
+        }
+        let mut payload = vec![0; length];
+        recv_stream.read_exact(&mut payload).await?;
+        Ok(Some(payload))
+    }
+    pub async fn close(self) {
+        if let Some(mut send_stream) = self.send_stream.lock().await.take()
+            && send_stream.finish().is_ok()
+        {
+            send_stream.stopped().await.ok();
+        }
+        self.connection.close(VarInt::from_u32(0), b"close");
+    }
+}
@@ -0,0 +1,211 @@
+use std::path::{Component, Path, PathBuf};
+
+use eyre::{Result, bail};
+use iroh::EndpointId;
+use iroh_blobs::{
+    Hash, HashAndFormat,
+    api::{Store, proto::BlobStatus},
+    format::collection::Collection,
+    protocol::{ChunkRanges, ChunkRangesExt, GetRequest},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{BlobOwner, Storage};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CollectionEntry {
+    pub name: String,
+    pub hash: String,
+    pub size: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SharedCollection {
+    pub hash: String,
+    pub entries: Vec<CollectionEntry>,
+}
+
+pub(crate) async fn share_directory(store: &Store, path: PathBuf) -> Result<SharedCollection> {
+    #[cfg(not(target_family = "wasm"))]
+    let (root, files) = tokio::task::spawn_blocking(move || scan(path)).await??;
+    #[cfg(target_family = "wasm")]
+    let (root, files) = scan(path)?;
+    let mut collection = Collection::default();
+    let mut temp_tags = Vec::with_capacity(files.len());
+    for file in files {
+        let name = file
+            .strip_prefix(&root)?
+            .components()
+            .map(|v| v.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let temp_tag = store.add_path(file).temp_tag().await?;
+        collection.push(name, temp_tag.hash());
+        temp_tags.push(temp_tag);
+    }
+    let temp_tag = collection.store(store).await?;
+    let hash = temp_tag.hash();
+    store
+        .tags()
+        .set(BlobOwner::Share.tag(hash), HashAndFormat::hash_seq(hash))
+        .await?;
+    describe(store, hash).await
+}
+
+fn scan(path: PathBuf) -> Result<(PathBuf, Vec<PathBuf>)> {
+    let root = path.canonicalize()?;
+    if !root.is_dir() {
+        bail!("路径不是目录");
+    }
+    let mut files = Vec::new();
+    walk(&root, &mut files)?;
+    files.sort();
+    Ok((root, files))
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn describe(store: &Store, root: Hash) -> Result<SharedCollection> {
+    let collection = Collection::load(root, store).await?;
+    let mut entries = Vec::with_capacity(collection.len());
+    for (name, hash) in collection.iter() {
+        let size = match store.status(*hash).await? {
+            BlobStatus::Complete { size } => Some(size),
+            BlobStatus::Partial { size } => size,
+            BlobStatus::NotFound => None,
+        };
+        entries.push(CollectionEntry {
+            name: name.clone(),
+            hash: hash.to_string(),
+            size,
+        });
+    }
+    Ok(SharedCollection {
+        hash: root.to_string(),
+        entries,
+    })
+}
+
+pub(crate) async fn list(
+    endpoint: &iroh::Endpoint,
+    storage: &Storage,
+    remote_id: EndpointId,
+    root: Hash,
+) -> Result<SharedCollection> {
+    let store = storage.store();
+    let request = GetRequest::builder()
+        .root(ChunkRanges::all())
+        .child(0, ChunkRanges::all())
+        .child(1, ChunkRanges::last_chunk())
+        .build_open(root);
+    if !store
+        .remote()
+        .local_for_request(request.clone())
+        .await?
+        .is_complete()
+    {
+        let connection = endpoint.connect(remote_id, iroh_blobs::ALPN).await?;
+        store
+            .remote()
+            .execute_get(connection, request)
+            .complete()
+            .await?;
+    }
+    storage.cache(HashAndFormat::hash_seq(root)).await?;
+    describe(store, root).await
+}
+
+pub(crate) fn request(root: Hash, len: usize, entries: Option<Vec<usize>>) -> Result<GetRequest> {
+    let mut builder = GetRequest::builder()
+        .root(ChunkRanges::all())
+        .child(0, ChunkRanges::all());
+    for index in entries.unwrap_or_else(|| (0..len).collect()) {
+        if index >= len {
+            bail!("集合条目不存在");
+        }
+        builder = builder.child(index as u64 + 1, ChunkRanges::all());
+    }
+    Ok(builder.build(root))
+}
+
+pub(crate) fn exports(
+    collection: &SharedCollection,
+    entries: Option<&[usize]>,
+    dest: &Path,
+) -> Result<Vec<(Hash, PathBuf)>> {
+    let mut exports = Vec::new();
+    for (index, entry) in collection.entries.iter().enumerate() {
+        if entries.is_some_and(|entries| !entries.contains(&index)) {
+            continue;
+        }
+        let name = Path::new(&entry.name);
+        if name.as_os_str().is_empty()
+            || !name.components().all(|v| matches!(v, Component::Normal(_)))
+        {
+            bail!("集合条目名称非法");
+        }
+        exports.push((entry.hash.parse()?, dest.join(name)));
+    }
+    Ok(exports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(names: &[&str]) -> SharedCollection {
+        SharedCollection {
+            hash: Hash::EMPTY.to_string(),
+            entries: names
+                .iter()
+                .map(|name| CollectionEntry {
+                    name: name.to_string(),
+                    hash: Hash::EMPTY.to_string(),
+                    size: Some(0),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn exports_entries_under_dest() {
+        let exports =
+            exports(&collection(&["a.txt", "dir/b.txt"]), None, Path::new("out")).unwrap();
+        assert_eq!(
+            exports
+                .into_iter()
+                .map(|(_, path)| path)
+                .collect::<Vec<_>>(),
+            [Path::new("out/a.txt"), Path::new("out/dir/b.txt")]
+        );
+    }
+
+    #[test]
+    fn rejects_path_traversal() {
+        for name in ["../evil", "dir/../../evil", "/etc/passwd", "./a", ""] {
+            assert!(
+                exports(&collection(&[name]), None, Path::new("out")).is_err(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn skips_unselected_entries() {
+        let exports =
+            exports(&collection(&["a", "../evil"]), Some(&[0]), Path::new("out")).unwrap();
+        assert_eq!(exports.len(), 1);
+    }
+}
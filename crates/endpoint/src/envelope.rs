@@ -0,0 +1,160 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use eyre::{Result, bail};
+use iroh::EndpointId;
+use n0_future::time::SystemTime;
+use person_protocol::MAX_CLOCK_SKEW;
+use serde::{Deserialize, Serialize};
+
+const REPLAY_WINDOW: u64 = 1024;
+const MAX_AUTHORS: usize = 4096;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Envelope {
+    pub sequence: u64,
+    pub timestamp: u64,
+    pub content: Vec<u8>,
+}
+impl Envelope {
+    pub fn new(sequence: u64, content: Vec<u8>) -> Result<Self> {
+        Ok(Self {
+            sequence,
+            timestamp: now()?,
+            content,
+        })
+    }
+    pub fn check(&self) -> Result<()> {
+        if now()?.abs_diff(self.timestamp) > MAX_CLOCK_SKEW.as_millis() as u64 {
+            bail!("群组消息时间戳无效");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Window {
+    floor: Option<u64>,
+    highest: u64,
+    seen: BTreeSet<u64>,
+    tick: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct ReplayGuard {
+    windows: HashMap<EndpointId, Window>,
+    recent: BTreeMap<u64, EndpointId>,
+    floors: HashMap<EndpointId, (u64, u64)>,
+    evicted: VecDeque<(u64, EndpointId)>,
+    tick: u64,
+}
+impl ReplayGuard {
+    pub fn check(&mut self, author: EndpointId, sequence: u64, now: u64) -> bool {
+        self.tick += 1;
+        while let Some((evicted_at, id)) = self.evicted.front().copied()
+            && now.saturating_sub(evicted_at) > 2 * MAX_CLOCK_SKEW.as_millis() as u64
+        {
+            self.evicted.pop_front();
+            if self.floors.get(&id).is_some_and(|v| v.1 == evicted_at) {
+                self.floors.remove(&id);
+            }
+        }
+        match self.windows.get(&author) {
+            Some(window) => {
+                self.recent.remove(&window.tick);
+            }
+            None if self.windows.len() >= MAX_AUTHORS => {
+                if let Some((_, oldest)) = self.recent.pop_first()
+                    && let Some(window) = self.windows.remove(&oldest)
+                {
+                    self.floors.insert(oldest, (window.highest, now));
+                    self.evicted.push_back((now, oldest));
+                }
+            }
+            None => {}
+        }
+        self.recent.insert(self.tick, author);
+        let window = self.windows.entry(author).or_insert_with(|| Window {
+            floor: self.floors.remove(&author).map(|v| v.0),
+            ..Default::default()
+        });
+        window.tick = self.tick;
+        if window.floor.is_some_and(|v| sequence <= v)
+            || sequence.saturating_add(REPLAY_WINDOW) <= window.highest
+            || !window.seen.insert(sequence)
+        {
+            return false;
+        }
+        window.highest = window.highest.max(sequence);
+        window.seen = window
+            .seen
+            .split_off(&window.highest.saturating_sub(REPLAY_WINDOW));
+        true
+    }
+}
+
+pub(crate) fn now() -> Result<u64> {
+    Ok(SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+
+    fn id() -> EndpointId {
+        SecretKey::generate(&mut rand::rng()).public()
+    }
+
+    #[test]
+    fn rejects_duplicates() {
+        let mut guard = ReplayGuard::default();
+        let author = id();
+        assert!(guard.check(author, 1, 0));
+        assert!(!guard.check(author, 1, 0));
+        assert!(guard.check(author, 2, 0));
+        assert!(guard.check(id(), 1, 0));
+    }
+
+    #[test]
+    fn rejects_sequences_outside_window() {
+        let mut guard = ReplayGuard::default();
+        let author = id();
+        assert!(guard.check(author, 2 * REPLAY_WINDOW, 0));
+        assert!(!guard.check(author, REPLAY_WINDOW, 0));
+        assert!(guard.check(author, REPLAY_WINDOW + 1, 0));
+        assert!(!guard.check(author, REPLAY_WINDOW + 1, 0));
+    }
+
+    #[test]
+    fn evicts_least_recent_author() {
+        let mut guard = ReplayGuard::default();
+        let authors: Vec<_> = (0..MAX_AUTHORS).map(|_| id()).collect();
+        for author in &authors {
+            assert!(guard.check(*author, 1, 0));
+        }
+        assert!(guard.check(authors[0], 2, 0));
+        assert!(guard.check(id(), 1, 0));
+        assert_eq!(guard.windows.len(), MAX_AUTHORS);
+        assert!(!guard.windows.contains_key(&authors[1]));
+        assert!(!guard.check(authors[0], 1, 0));
+        assert!(!guard.check(authors[1], 1, 0));
+        assert!(guard.check(authors[1], 2, 0));
+    }
+
+    #[test]
+    fn forgets_evicted_authors_after_skew() {
+        let mut guard = ReplayGuard::default();
+        let authors: Vec<_> = (0..=MAX_AUTHORS).map(|_| id()).collect();
+        for author in &authors {
+            assert!(guard.check(*author, 1, 0));
+        }
+        assert!(guard.floors.contains_key(&authors[0]));
+        let later = 2 * MAX_CLOCK_SKEW.as_millis() as u64 + 1;
+        assert!(guard.check(id(), 1, later));
+        assert!(!guard.floors.contains_key(&authors[0]));
+        assert_eq!(guard.evicted.len(), 1);
+    }
+}
@@ -0,0 +1,99 @@
+use person_protocol::Person;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum PersonProtocolEventKind {
+    FriendRequest,
+    ChatRequest,
+    RequestExpired,
+    Unfriended,
+    PersonUpdated,
+    GroupInvite,
+}
+impl From<&person_protocol::Event> for PersonProtocolEventKind {
+    fn from(event: &person_protocol::Event) -> Self {
+        match event {
+            person_protocol::Event::FriendRequest(_) => PersonProtocolEventKind::FriendRequest,
+            person_protocol::Event::ChatRequest(_) => PersonProtocolEventKind::ChatRequest,
+            person_protocol::Event::RequestExpired(_) => PersonProtocolEventKind::RequestExpired,
+            person_protocol::Event::Unfriended(_) => PersonProtocolEventKind::Unfriended,
+            person_protocol::Event::PersonUpdated(_) => PersonProtocolEventKind::PersonUpdated,
+            person_protocol::Event::GroupInvite(_) => PersonProtocolEventKind::GroupInvite,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Introduction {
+    pub greeting: Option<String>,
+    pub person: Person,
+    pub sent_at: u64,
+}
+impl Introduction {
+    pub fn from_event(event: &person_protocol::Event) -> Option<Self> {
+        let person_protocol::Event::FriendRequest(friend_request) = event else {
+            return None;
+        };
+        Some(Self {
+            greeting: friend_request.greeting().map(str::to_string),
+            person: friend_request.person()?.clone(),
+            sent_at: friend_request.sent_at()?,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PersonProfile {
+    pub person: Person,
+    pub version: u64,
+}
+impl From<person_protocol::Profile> for PersonProfile {
+    fn from(profile: person_protocol::Profile) -> Self {
+        Self {
+            person: profile.person,
+            version: profile.version,
+        }
+    }
+}
+impl PersonProfile {
+    pub fn from_event(event: &person_protocol::Event) -> Option<Self> {
+        let person_protocol::Event::PersonUpdated(person_updated) = event else {
+            return None;
+        };
+        Some(person_updated.profile().clone().into())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Invitation {
+    pub ticket: String,
+    pub group_name: String,
+}
+impl Invitation {
+    pub fn from_event(event: &person_protocol::Event) -> Option<Self> {
+        let person_protocol::Event::GroupInvite(group_invite) = event else {
+            return None;
+        };
+        Some(Self {
+            ticket: group_invite.ticket().to_string(),
+            group_name: group_invite.group_name().to_string(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PersonProtocolEvent {
+    pub id: Option<usize>,
+    pub kind: PersonProtocolEventKind,
+    pub remote_id: String,
+    pub timestamp: u64,
+    pub introduction: Option<Introduction>,
+    pub profile: Option<PersonProfile>,
+    pub invitation: Option<Invitation>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum EventResponse {
+    Accept,
+    Reject,
+}
@@ -0,0 +1,739 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use eyre::{Result, bail, eyre};
+use futures::{StreamExt, lock::Mutex};
+use iroh::{EndpointId, SecretKey};
+use iroh_gossip::{
+    Gossip, TopicId,
+    api::{Event, GossipReceiver, GossipSender},
+};
+use person_protocol::{GroupKey, PersonProtocol, keyring::key_hash};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    envelope::{self, Envelope, ReplayGuard},
+    membership::{GroupAction, GroupMembership, Membership, Signed},
+    seal::Sealed,
+    ticket::GroupTicket,
+};
+
+const FRAME_MAGIC: &[u8] = b"dp2p/group/4";
+const MESSAGE_DOMAIN: &[u8] = b"dp2p/group-message";
+const MEMBERSHIP_DOMAIN: &[u8] = b"dp2p/group-membership";
+const JOIN_DOMAIN: &[u8] = b"dp2p/group-join";
+const SEALED_DOMAIN: &[u8] = b"dp2p/group-sealed";
+const KEY_DELIVERY_ATTEMPTS: u32 = 5;
+const KEY_DELIVERY_DELAY: Duration = Duration::from_secs(2);
+const MAX_DEFERRED_FRAMES: usize = 256;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum GroupEvent {
+    Message {
+        content: Vec<u8>,
+        delivered_from: String,
+        author: String,
+        sequence: u64,
+        timestamp: u64,
+    },
+    NeighborUp {
+        id: String,
+    },
+    NeighborDown {
+        id: String,
+    },
+    MembershipChanged {
+        membership: GroupMembership,
+    },
+    Removed,
+    Lagged,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Join {
+    ticket: String,
+    timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Frame {
+    Membership(Box<Signed<Membership>>),
+    Join(Signed<Join>),
+    Sealed(Sealed),
+    Plain(Signed<Envelope>),
+}
+
+struct State {
+    topic: TopicId,
+    name: String,
+    membership: Option<Signed<Membership>>,
+    anchor: Option<EndpointId>,
+    group: TopicId,
+    ticket: Option<String>,
+}
+impl State {
+    fn is_managed(&self) -> bool {
+        self.anchor.is_some()
+    }
+    fn check(&self, signed: &Signed<Membership>) -> Result<()> {
+        match &self.membership {
+            Some(current) => current.body.check_transition(&signed.body, signed.author),
+            None => {
+                signed.body.check()?;
+                if signed.body.topic != self.topic
+                    || signed.body.group != self.group
+                    || !self.anchor.is_some_and(|v| signed.body.is_admin(&v))
+                    || !signed.body.is_admin(&signed.author)
+                {
+                    bail!("群组成员文档不可信");
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+enum Wake {
+    Received(Option<Result<Event, iroh_gossip::api::ApiError>>),
+    Rotated,
+    Key([u8; 32], u64),
+}
+
+#[derive(Clone)]
+pub(crate) struct GroupContext {
+    pub gossip: Gossip,
+    pub secret_key: SecretKey,
+    pub person_protocol: PersonProtocol,
+}
+
+#[derive(Clone)]
+pub struct Group {
+    context: GroupContext,
+    state: Arc<parking_lot::Mutex<State>>,
+    sender: Arc<parking_lot::Mutex<GossipSender>>,
+    receiver: Arc<Mutex<GossipReceiver>>,
+    pending_receiver: Arc<parking_lot::Mutex<Option<GossipReceiver>>>,
+    rotated: (async_channel::Sender<()>, async_channel::Receiver<()>),
+    neighbors: Arc<parking_lot::Mutex<BTreeSet<EndpointId>>>,
+    sequence: Arc<AtomicU64>,
+    replay: Arc<parking_lot::Mutex<ReplayGuard>>,
+    keys: async_channel::Receiver<([u8; 32], u64)>,
+    deferred: Arc<parking_lot::Mutex<VecDeque<(Sealed, EndpointId)>>>,
+    ready: Arc<parking_lot::Mutex<VecDeque<GroupEvent>>>,
+}
+impl Group {
+    pub(crate) async fn join(
+        context: GroupContext,
+        topic: TopicId,
+        name: String,
+        bootstrap: Vec<EndpointId>,
+        anchor: Option<EndpointId>,
+        group: TopicId,
+        ticket: Option<String>,
+    ) -> Result<Self> {
+        let (sender, receiver) = context.gossip.subscribe(topic, bootstrap).await?.split();
+        let keys = context.person_protocol.keyring().subscribe();
+        Ok(Self {
+            context,
+            state: Arc::new(parking_lot::Mutex::new(State {
+                topic,
+                name,
+                membership: None,
+                anchor,
+                group,
+                ticket,
+            })),
+            sender: Arc::new(parking_lot::Mutex::new(sender)),
+            receiver: Arc::new(Mutex::new(receiver)),
+            pending_receiver: Default::default(),
+            rotated: async_channel::bounded(1),
+            neighbors: Default::default(),
+            sequence: Arc::new(AtomicU64::new(envelope::now()?)),
+            replay: Default::default(),
+            keys,
+            deferred: Default::default(),
+            ready: Default::default(),
+        })
+    }
+    pub(crate) async fn create(context: GroupContext, name: String) -> Result<Self> {
+        let id = context.secret_key.public();
+        let key = rand::random();
+        let membership = Membership::new(id, name.clone(), key_hash(&key));
+        let signed = Signed::new(&context.secret_key, MEMBERSHIP_DOMAIN, membership)?;
+        context.person_protocol.keyring().set(
+            *signed.body.group.as_bytes(),
+            signed.body.epoch,
+            key,
+        );
+        let group = Self::join(
+            context,
+            signed.body.topic,
+            name,
+            Vec::new(),
+            Some(id),
+            signed.body.group,
+            None,
+        )
+        .await?;
+        group.state.lock().membership = Some(signed);
+        Ok(group)
+    }
+    pub fn id(&self) -> TopicId {
+        self.state.lock().topic
+    }
+    pub fn group(&self) -> TopicId {
+        self.state.lock().group
+    }
+    pub fn epoch(&self) -> u64 {
+        self.state
+            .lock()
+            .membership
+            .as_ref()
+            .map_or(0, |v| v.body.epoch)
+    }
+    pub fn name(&self) -> String {
+        self.state.lock().name.clone()
+    }
+    pub fn membership(&self) -> Option<GroupMembership> {
+        self.state.lock().membership.as_ref().map(|v| v.body.view())
+    }
+    pub fn check_inviter(&self) -> Result<()> {
+        if !self
+            .state
+            .lock()
+            .membership
+            .as_ref()
+            .is_some_and(|v| v.body.is_admin(&self.context.secret_key.public()))
+        {
+            bail!("只有群组管理员可以邀请成员");
+        }
+        Ok(())
+    }
+    pub async fn manage(&self, action: GroupAction) -> Result<()> {
+        let Some(membership) = self
+            .state
+            .lock()
+            .membership
+            .as_ref()
+            .map(|v| v.body.clone())
+        else {
+            bail!("群组未启用成员管理");
+        };
+        let id = self.context.secret_key.public();
+        let mut next = membership.apply(id, action)?;
+        if !membership.removed(&next).is_empty() {
+            let key = rand::random();
+            next.rotate(key_hash(&key));
+            membership.check_transition(&next, id)?;
+            self.context
+                .person_protocol
+                .keyring()
+                .set(*next.group.as_bytes(), next.epoch, key);
+        }
+        self.publish(next).await?;
+        Ok(())
+    }
+    pub async fn broadcast(&self, payload: Vec<u8>) -> Result<()> {
+        let (topic, sealing) = {
+            let state = self.state.lock();
+            let sealing = if state.is_managed() {
+                let Some(membership) = state
+                    .membership
+                    .as_ref()
+                    .filter(|v| v.body.is_member(&self.context.secret_key.public()))
+                else {
+                    bail!("你不是群组成员");
+                };
+                Some((membership.body.group, membership.body.epoch))
+            } else {
+                None
+            };
+            (state.topic, sealing)
+        };
+        let message = Signed::new(
+            &self.context.secret_key,
+            &domain(MESSAGE_DOMAIN, topic),
+            Envelope::new(self.sequence.fetch_add(1, Ordering::Relaxed), payload)?,
+        )?;
+        let Some((group, epoch)) = sealing else {
+            return self.send(Frame::Plain(message), false).await;
+        };
+        let key = self
+            .context
+            .person_protocol
+            .keyring()
+            .get(*group.as_bytes(), epoch)
+            .ok_or_else(|| eyre!("尚未获得群组密钥"))?;
+        let frame = Frame::Sealed(Sealed::seal(
+            &key,
+            &domain(SEALED_DOMAIN, group),
+            epoch,
+            &postcard::to_stdvec(&message)?,
+        )?);
+        self.send(frame, false).await
+    }
+    pub async fn next_event(&self) -> Result<Option<GroupEvent>> {
+        let mut receiver = self.receiver.lock().await;
+        loop {
+            if let Some(event) = self.ready.lock().pop_front() {
+                return Ok(Some(event));
+            }
+            if let Some(pending_receiver) = self.pending_receiver.lock().take() {
+                *receiver = pending_receiver;
+            }
+            let wake = futures_lite::future::or(
+                async { Wake::Received(receiver.next().await) },
+                futures_lite::future::or(
+                    async {
+                        self.rotated.1.recv().await.ok();
+                        Wake::Rotated
+                    },
+                    async {
+                        match self.keys.recv().await {
+                            Ok((group, epoch)) => Wake::Key(group, epoch),
+                            Err(_) => std::future::pending().await,
+                        }
+                    },
+                ),
+            )
+            .await;
+            let item = match wake {
+                Wake::Received(item) => item,
+                Wake::Rotated => continue,
+                Wake::Key(group, epoch) => {
+                    self.replay_deferred(group, epoch);
+                    continue;
+                }
+            };
+            let Some(event) = item.transpose()? else {
+                return Ok(None);
+            };
+            let event = match event {
+                Event::Received(message) => {
+                    match self
+                        .handle(message.content.to_vec(), message.delivered_from)
+                        .await
+                    {
+                        Ok(Some(event)) => event,
+                        Ok(None) => continue,
+                        Err(err) => {
+                            log::warn!("丢弃无效的群组消息：{}", err);
+                            continue;
+                        }
+                    }
+                }
+                Event::NeighborUp(id) => {
+                    self.neighbors.lock().insert(id);
+                    if let Err(err) = self.greet(id).await {
+                        log::warn!("向群组邻居发送成员信息失败：{}", err);
+                    }
+                    GroupEvent::NeighborUp { id: id.to_string() }
+                }
+                Event::NeighborDown(id) => {
+                    self.neighbors.lock().remove(&id);
+                    GroupEvent::NeighborDown { id: id.to_string() }
+                }
+                Event::Lagged => GroupEvent::Lagged,
+            };
+            return Ok(Some(event));
+        }
+    }
+    pub fn neighbors(&self) -> Vec<String> {
+        self.neighbors
+            .lock()
+            .iter()
+            .map(|v| v.to_string())
+            .collect()
+    }
+    async fn handle(
+        &self,
+        content: Vec<u8>,
+        delivered_from: EndpointId,
+    ) -> Result<Option<GroupEvent>> {
+        let Some(bytes) = content.strip_prefix(FRAME_MAGIC) else {
+            return Ok(None);
+        };
+        let (managed, group) = {
+            let state = self.state.lock();
+            (state.is_managed(), state.group)
+        };
+        match postcard::from_bytes(bytes)? {
+            Frame::Plain(message) if !managed => self.receive(message, delivered_from),
+            Frame::Plain(_) => Ok(None),
+            Frame::Sealed(_) if !managed => Ok(None),
+            Frame::Sealed(sealed) => {
+                let Some(key) = self
+                    .context
+                    .person_protocol
+                    .keyring()
+                    .get(*group.as_bytes(), sealed.epoch)
+                else {
+                    let mut deferred = self.deferred.lock();
+                    if deferred.len() >= MAX_DEFERRED_FRAMES {
+                        deferred.pop_front();
+                    }
+                    deferred.push_back((sealed, delivered_from));
+                    return Ok(None);
+                };
+                self.open(&key, group, sealed, delivered_from)
+            }
+            Frame::Membership(membership) => self.accept(*membership).await,
+            Frame::Join(join) => self.admit(join).await,
+        }
+    }
+    fn open(
+        &self,
+        key: &[u8; 32],
+        group: TopicId,
+        sealed: Sealed,
+        delivered_from: EndpointId,
+    ) -> Result<Option<GroupEvent>> {
+        let message = postcard::from_bytes(&sealed.open(key, &domain(SEALED_DOMAIN, group))?)?;
+        self.receive(message, delivered_from)
+    }
+    fn replay_deferred(&self, group: [u8; 32], epoch: u64) {
+        let topic = self.state.lock().group;
+        if group != *topic.as_bytes() {
+            return;
+        }
+        let Some(key) = self.context.person_protocol.keyring().get(group, epoch) else {
+            return;
+        };
+        let frames = {
+            let mut deferred = self.deferred.lock();
+            let (frames, rest) = std::mem::take(&mut *deferred)
+                .into_iter()
+                .partition::<VecDeque<_>, _>(|(sealed, _)| sealed.epoch == epoch);
+            *deferred = rest;
+            frames
+        };
+        for (sealed, delivered_from) in frames {
+            match self.open(&key, topic, sealed, delivered_from) {
+                Ok(Some(event)) => self.ready.lock().push_back(event),
+                Ok(None) => {}
+                Err(err) => log::warn!("丢弃无效的群组消息：{}", err),
+            }
+        }
+    }
+    fn receive(
+        &self,
+        message: Signed<Envelope>,
+        delivered_from: EndpointId,
+    ) -> Result<Option<GroupEvent>> {
+        let topic = {
+            let state = self.state.lock();
+            if state
+                .membership
+                .as_ref()
+                .is_some_and(|v| !v.body.is_member(&message.author))
+            {
+                return Ok(None);
+            }
+            state.topic
+        };
+        message.verify(&domain(MESSAGE_DOMAIN, topic))?;
+        message.body.check()?;
+        if !self
+            .replay
+            .lock()
+            .check(message.author, message.body.sequence, envelope::now()?)
+        {
+            return Ok(None);
+        }
+        Ok(Some(GroupEvent::Message {
+            content: message.body.content,
+            delivered_from: delivered_from.to_string(),
+            author: message.author.to_string(),
+            sequence: message.body.sequence,
+            timestamp: message.body.timestamp,
+        }))
+    }
+    async fn accept(&self, signed: Signed<Membership>) -> Result<Option<GroupEvent>> {
+        signed.verify(MEMBERSHIP_DOMAIN)?;
+        let id = self.context.secret_key.public();
+        let (event, rotated) = {
+            let mut state = self.state.lock();
+            if state
+                .membership
+                .as_ref()
+                .is_some_and(|v| v.body == signed.body)
+            {
+                return Ok(None);
+            }
+            state.check(&signed)?;
+            let was_member = state
+                .membership
+                .as_ref()
+                .is_some_and(|v| v.body.is_member(&id));
+            let is_member = signed.body.is_member(&id);
+            let rotated = state.topic != signed.body.topic;
+            let keyring = self.context.person_protocol.keyring();
+            let event = if was_member && !is_member {
+                keyring.remove(*signed.body.group.as_bytes());
+                GroupEvent::Removed
+            } else {
+                keyring.want(
+                    *signed.body.group.as_bytes(),
+                    signed.body.epoch,
+                    signed.body.key_hash,
+                    signed
+                        .body
+                        .members
+                        .keys()
+                        .filter(|v| signed.body.is_admin(v))
+                        .copied(),
+                );
+                GroupEvent::MembershipChanged {
+                    membership: signed.body.view(),
+                }
+            };
+            state.topic = signed.body.topic;
+            state.name = signed.body.name.clone();
+            if is_member {
+                state.ticket = None;
+            }
+            state.membership = Some(signed);
+            (event, rotated)
+        };
+        if rotated {
+            self.resubscribe().await?;
+        }
+        Ok(Some(event))
+    }
+    async fn admit(&self, join: Signed<Join>) -> Result<Option<GroupEvent>> {
+        let (topic, membership) = {
+            let state = self.state.lock();
+            (
+                state.topic,
+                state.membership.as_ref().map(|v| v.body.clone()),
+            )
+        };
+        join.verify(&domain(JOIN_DOMAIN, topic))?;
+        let ticket = GroupTicket::deserialize(&join.body.ticket)?;
+        let id = self.context.secret_key.public();
+        if ticket.inviter != id {
+            return Ok(None);
+        }
+        let Some(membership) = membership else {
+            return Ok(None);
+        };
+        match membership.admit(id, join.author, &ticket, join.body.timestamp)? {
+            Some(next) => self.publish(next).await,
+            None => Ok(None),
+        }
+    }
+    async fn publish(&self, membership: Membership) -> Result<Option<GroupEvent>> {
+        let id = self.context.secret_key.public();
+        let recipients = {
+            let state = self.state.lock();
+            let current = state.membership.as_ref().map(|v| &v.body);
+            membership
+                .members
+                .keys()
+                .filter(|v| {
+                    **v != id
+                        && current.is_none_or(|c| c.epoch != membership.epoch || !c.is_member(v))
+                })
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        let (group, epoch) = (membership.group, membership.epoch);
+        let signed = Signed::new(&self.context.secret_key, MEMBERSHIP_DOMAIN, membership)?;
+        self.state.lock().check(&signed)?;
+        self.send(Frame::Membership(Box::new(signed.clone())), false)
+            .await?;
+        let event = self.accept(signed).await?;
+        self.distribute(group, epoch, recipients);
+        Ok(event)
+    }
+    fn distribute(&self, group: TopicId, epoch: u64, recipients: Vec<EndpointId>) {
+        let group = *group.as_bytes();
+        let Some(key) = self.context.person_protocol.keyring().get(group, epoch) else {
+            return;
+        };
+        let key = GroupKey { group, epoch, key };
+        for id in recipients {
+            let person_protocol = self.context.person_protocol.clone();
+            n0_future::task::spawn(async move {
+                for attempt in 1..=KEY_DELIVERY_ATTEMPTS {
+                    match person_protocol.send_group_key(id, key).await {
+                        Ok(true) => return,
+                        Ok(false) => {}
+                        Err(err) => log::warn!("向{}发送群组密钥失败：{}", id, err),
+                    }
+                    n0_future::time::sleep(KEY_DELIVERY_DELAY * attempt).await;
+                }
+                log::warn!("{}未接收群组密钥", id);
+            });
+        }
+    }
+    async fn greet(&self, neighbor: EndpointId) -> Result<()> {
+        let (topic, membership, ticket) = {
+            let state = self.state.lock();
+            (
+                state.topic,
+                state.membership.clone(),
+                state
+                    .ticket
+                    .clone()
+                    .filter(|_| state.anchor == Some(neighbor)),
+            )
+        };
+        if let Some(membership) = membership {
+            if membership.body.is_admin(&self.context.secret_key.public())
+                && membership.body.is_member(&neighbor)
+            {
+                self.distribute(membership.body.group, membership.body.epoch, vec![neighbor]);
+            }
+            self.send(Frame::Membership(Box::new(membership)), true)
+                .await?;
+        }
+        if let Some(ticket) = ticket {
+            let join = Join {
+                ticket,
+                timestamp: envelope::now()?,
+            };
+            self.send(
+                Frame::Join(Signed::new(
+                    &self.context.secret_key,
+                    &domain(JOIN_DOMAIN, topic),
+                    join,
+                )?),
+                true,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+    async fn resubscribe(&self) -> Result<()> {
+        let (topic, members) = {
+            let state = self.state.lock();
+            (
+                state.topic,
+                state
+                    .membership
+                    .as_ref()
+                    .map(|v| v.body.members.keys().copied().collect::<Vec<_>>())
+                    .unwrap_or_default(),
+            )
+        };
+        let id = self.context.secret_key.public();
+        let bootstrap = members
+            .into_iter()
+            .chain(self.neighbors.lock().iter().copied())
+            .filter(|v| *v != id)
+            .collect::<BTreeSet<_>>();
+        let (sender, receiver) = self
+            .context
+            .gossip
+            .subscribe(topic, bootstrap.into_iter().collect())
+            .await?
+            .split();
+        *self.sender.lock() = sender;
+        *self.pending_receiver.lock() = Some(receiver);
+        self.neighbors.lock().clear();
+        self.rotated.0.try_send(()).ok();
+        Ok(())
+    }
+    async fn send(&self, frame: Frame, neighbors_only: bool) -> Result<()> {
+        let mut bytes = FRAME_MAGIC.to_vec();
+        bytes.extend_from_slice(&postcard::to_stdvec(&frame)?);
+        let sender = self.sender.lock().clone();
+        if neighbors_only {
+            sender.broadcast_neighbors(bytes.into()).await?;
+        } else {
+            sender.broadcast(bytes.into()).await?;
+        }
+        Ok(())
+    }
+}
+
+fn domain(domain: &[u8], topic: TopicId) -> Vec<u8> {
+    let mut bytes = domain.to_vec();
+    bytes.extend_from_slice(topic.as_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+    use crate::{
+        AddressLookupOption, Endpoint, LocalPerson, RelayOption, StoreOption, policy::PolicyOption,
+        ticket::Ticket,
+    };
+
+    async fn endpoint() -> Endpoint {
+        Endpoint::builder(
+            SecretKey::generate(&mut rand::rng()).to_bytes().to_vec(),
+            LocalPerson {
+                name: "测试".to_string(),
+                avatar: None,
+                bio: String::new(),
+            },
+        )
+        .relay(RelayOption::Disabled)
+        .address_lookup(AddressLookupOption::disabled())
+        .store(StoreOption::Memory)
+        .policy(PolicyOption::Memory)
+        .bind()
+        .await
+        .unwrap()
+    }
+
+    async fn next_message(endpoint: &Endpoint, handle: usize) -> Vec<u8> {
+        loop {
+            match endpoint.group_next_event(handle).await.unwrap().unwrap() {
+                GroupEvent::Message { content, .. } => return content,
+                GroupEvent::NeighborUp { .. } => {}
+                _ => panic!("意外的群组事件"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn joins_unmanaged_group_from_legacy_ticket() {
+        let alice = endpoint().await;
+        let bob = endpoint().await;
+        let ticket = Ticket {
+            id: TopicId::from_bytes(rand::random()),
+            bootstrap: vec![alice.router.endpoint().id()],
+        }
+        .serialize()
+        .unwrap();
+        bob.memory_lookup
+            .add_endpoint_info(alice.router.endpoint().addr());
+
+        let alice_group = alice.subscribe_group(ticket.clone()).await.unwrap();
+        let bob_group = bob.subscribe_group(ticket).await.unwrap();
+        assert!(alice.group_membership(alice_group).unwrap().is_none());
+        let events = n0_future::task::spawn({
+            let bob = bob.clone();
+            async move { next_message(&bob, bob_group).await }
+        });
+        assert!(matches!(
+            alice.group_next_event(alice_group).await.unwrap().unwrap(),
+            GroupEvent::NeighborUp { .. }
+        ));
+        alice
+            .group_broadcast(alice_group, b"hello".to_vec())
+            .await
+            .unwrap();
+        let content = n0_future::time::timeout(Duration::from_secs(10), events)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(content, b"hello");
+
+        alice.close().await.unwrap();
+        bob.close().await.unwrap();
+    }
+}
@@ -1,88 +1,105 @@
-use std::sync::Arc;
+mod access;
+mod avatar;
+mod builder;
+mod chat;
+mod collection;
+mod envelope;
+mod event;
+mod group;
+mod membership;
+mod policy;
+mod seal;
+mod storage;
+mod ticket;
+mod transfer;
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
 
-use base64::{Engine, prelude::BASE64_STANDARD};
 use eyre::Result;
-use iroh::{
-    EndpointId, RelayConfig, RelayMode, SecretKey, address_lookup::PkarrPublisher,
-    endpoint::Connection, protocol::Router,
-};
-use iroh_blobs::{BlobsProtocol, api::Store};
-use iroh_gossip::{
-    Gossip, TopicId,
-    api::{GossipReceiver, GossipSender},
-};
-use iroh_relay::RelayQuicConfig;
-use parking_lot::Mutex;
-use person_protocol::{Person, PersonProtocol};
-use serde::{Deserialize, Serialize};
+use iroh::{EndpointAddr, address_lookup::MemoryLookup, protocol::Router};
+use iroh_blobs::{BlobsProtocol, HashAndFormat, protocol::GetRequest};
+use iroh_gossip::Gossip;
+use n0_future::task::AbortOnDropHandle;
+use person_protocol::{GroupInvitation, PersonProtocol, limit::Limits, policy::Policy};
 use sharded_slab::Slab;
 use utils::option_ext::OptionGet;
 
-#[derive(Serialize, Deserialize)]
-pub struct Ticket {
-    pub id: TopicId,
-    pub bootstrap: Vec<EndpointId>,
-}
+use crate::{
+    access::BlobAccess,
+    chat::Chat,
+    group::{Group, GroupContext},
+    storage::Storage,
+    ticket::ParsedTicket,
+    transfer::Transfer,
+};
+
+#[cfg(not(target_family = "wasm"))]
+pub use crate::policy::FsPolicyStore;
+pub use crate::{
+    avatar::LocalPerson,
+    builder::{
+        AddressLookupOption, EndpointBuilder, EndpointConfig, RelayOption, RelayServer, StoreOption,
+    },
+    collection::{CollectionEntry, SharedCollection},
+    event::{
+        EventResponse, Introduction, Invitation, PersonProfile, PersonProtocolEvent,
+        PersonProtocolEventKind,
+    },
+    group::GroupEvent,
+    membership::{GroupAction, GroupMember, GroupMembership, Role},
+    policy::PolicyOption,
+    storage::{BlobOwner, StorageUsage},
+    ticket::{GroupTicket, Ticket, TicketInfo, TicketOptions},
+    transfer::{SharedBlob, TransferEvent},
+};
+pub use person_protocol::{
+    Avatar, Outcome, Person, Timeouts,
+    limit::{Metrics, OverflowPolicy, RateLimit},
+    privacy::{Privacy, Visibility},
+};
+
+const INVITE_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
 
 #[derive(Clone)]
 pub struct Endpoint {
     router: Router,
     person_protocol: PersonProtocol,
     gossip_protocol: Gossip,
-    _blobs_protocol: BlobsProtocol,
-    connection_pool: Arc<Slab<Connection>>,
-    person_protocol_event: Arc<Mutex<Option<person_protocol::Event>>>,
-    group_pool: Arc<Slab<(GossipSender, GossipReceiver)>>,
+    memory_lookup: MemoryLookup,
+    storage: Storage,
+    chat_pool: Arc<Slab<Chat>>,
+    pending_events: Arc<Slab<person_protocol::Event>>,
+    event_ids: Arc<parking_lot::Mutex<HashMap<u64, usize>>>,
+    group_pool: Arc<Slab<Group>>,
+    transfer_pool: Arc<Slab<Transfer>>,
+    _blob_access: Arc<AbortOnDropHandle<()>>,
 }
 impl Endpoint {
-    pub async fn new(secret_key: Vec<u8>, person: Person) -> Result<Self> {
-        let relay_map = RelayMode::Default.relay_map();
-        relay_map.insert(
-            "https://dev.zhangxichang.com:10281".parse()?,
-            RelayConfig {
-                url: "https://dev.zhangxichang.com:10281".parse()?,
-                quic: Some(RelayQuicConfig { port: 10282 }),
-            }
-            .into(),
-        );
-        #[allow(unused_mut)]
-        let mut endpoint_builder = iroh::Endpoint::empty_builder(RelayMode::Custom(relay_map))
-            .address_lookup(PkarrPublisher::n0_dns());
-        #[cfg(not(target_family = "wasm"))]
-        {
-            use iroh::address_lookup::{DhtAddressLookup, DnsAddressLookup, MdnsAddressLookup};
-
-            endpoint_builder = endpoint_builder
-                .address_lookup(DnsAddressLookup::n0_dns())
-                .address_lookup(MdnsAddressLookup::builder())
-                .address_lookup(DhtAddressLookup::builder());
-        }
-        let endpoint = endpoint_builder
-            .secret_key(SecretKey::from_bytes(secret_key.as_slice().try_into()?))
-            .bind()
-            .await?;
-        let person_protocol = PersonProtocol::new(endpoint.clone(), person);
+    pub fn builder(secret_key: Vec<u8>, person: LocalPerson) -> EndpointBuilder {
+        EndpointBuilder::new(secret_key, person)
+    }
+    pub async fn new(secret_key: Vec<u8>, person: LocalPerson) -> Result<Self> {
+        Self::builder(secret_key, person).bind().await
+    }
+    fn spawn(
+        endpoint: iroh::Endpoint,
+        person: Person,
+        storage: Storage,
+        timeouts: Timeouts,
+        limits: Limits,
+        policy: Policy,
+    ) -> Result<Self> {
+        let person_protocol =
+            PersonProtocol::new(endpoint.clone(), person, timeouts, limits, policy)?;
         let gossip_protocol = Gossip::builder().spawn(endpoint.clone());
-        let store: Store;
-        #[cfg(not(target_family = "wasm"))]
-        {
-            use eyre::eyre;
-            use iroh_blobs::store::fs::FsStore;
-
-            store = FsStore::load("store")
-                .await
-                .map_err(|err| eyre!(err))?
-                .into();
-        }
-        #[cfg(target_family = "wasm")]
-        {
-            use iroh_blobs::store::mem::MemStore;
-
-            store = MemStore::new().into();
-        }
-        let blobs_protocol = BlobsProtocol::new(&store, None);
+        let memory_lookup = MemoryLookup::new();
+        endpoint.address_lookup().add(memory_lookup.clone());
+        let (blob_events, blob_access) =
+            BlobAccess::spawn(person_protocol.clone(), storage.store().clone());
+        let blobs_protocol = BlobsProtocol::new(storage.store(), Some(blob_events));
         let router = Router::builder(endpoint)
             .accept(person_protocol::ALPN, person_protocol.clone())
+            .accept(person_protocol::v1::ALPN, person_protocol.clone())
             .accept(iroh_gossip::ALPN, gossip_protocol.clone())
             .accept(iroh_blobs::ALPN, blobs_protocol.clone())
             .spawn();
@@ -90,10 +107,14 @@ impl Endpoint {
             router,
             person_protocol,
             gossip_protocol,
-            _blobs_protocol: blobs_protocol,
-            connection_pool: Default::default(),
-            person_protocol_event: Default::default(),
+            memory_lookup,
+            storage,
+            chat_pool: Default::default(),
+            pending_events: Default::default(),
+            event_ids: Default::default(),
             group_pool: Default::default(),
+            transfer_pool: Default::default(),
+            _blob_access: blob_access,
         })
     }
     pub async fn close(self) -> Result<()> {
@@ -103,57 +124,451 @@ impl Endpoint {
     pub fn id(&self) -> String {
         self.router.endpoint().id().to_string()
     }
-    pub async fn person_protocol_next_event(&self) -> Result<String> {
-        let event = self.person_protocol.next_event().await?;
-        let event_type = event.to_string();
-        self.person_protocol_event.lock().replace(event);
-        Ok(event_type)
-    }
-    pub fn person_protocol_event(&self, method: String) -> Result<serde_json::Value> {
-        match self.person_protocol_event.lock().take().get()? {
-            person_protocol::Event::FriendRequest(friend_request) => match method.as_ref() {
-                "remote_id" => return Ok(friend_request.remote_id().to_string().into()),
-                "accept" => friend_request.accept()?,
-                "reject" => friend_request.reject()?,
-                _ => (),
-            },
-            person_protocol::Event::ChatRequest(chat_request) => match method.as_ref() {
-                "remote_id" => return Ok(chat_request.remote_id().to_string().into()),
-                "accept" => {
-                    return Ok(self
-                        .connection_pool
-                        .insert(chat_request.accept()?)
-                        .get()?
-                        .into());
+    pub async fn next_event(&self) -> Result<PersonProtocolEvent> {
+        loop {
+            let event = self.person_protocol.next_event().await?;
+            let kind = PersonProtocolEventKind::from(&event);
+            let request_id = event.request_id();
+            let remote_id = event.remote_id().to_string();
+            let timestamp = event.timestamp();
+            let introduction = Introduction::from_event(&event);
+            let profile = PersonProfile::from_event(&event);
+            let invitation = Invitation::from_event(&event);
+            let id = match event {
+                person_protocol::Event::RequestExpired(_) => {
+                    let Some(id) = self.event_ids.lock().remove(&request_id) else {
+                        continue;
+                    };
+                    self.pending_events.remove(id);
+                    Some(id)
                 }
-                "reject" => chat_request.reject()?,
-                _ => (),
-            },
+                person_protocol::Event::Unfriended(_)
+                | person_protocol::Event::PersonUpdated(_) => None,
+                event => {
+                    let id = self.pending_events.insert(event).get()?;
+                    self.event_ids.lock().insert(request_id, id);
+                    Some(id)
+                }
+            };
+            return Ok(PersonProtocolEvent {
+                id,
+                kind,
+                remote_id,
+                timestamp,
+                introduction,
+                profile,
+                invitation,
+            });
         }
-        Ok(().into())
     }
-    pub async fn request_person(&self, id: String) -> Result<Person> {
-        Ok(self.person_protocol.request_person(id.parse()?).await?)
+    pub async fn respond(&self, event_id: usize, response: EventResponse) -> Result<Option<usize>> {
+        let event = self.pending_events.take(event_id).get()?;
+        self.event_ids.lock().remove(&event.request_id());
+        match (event, response) {
+            (person_protocol::Event::FriendRequest(friend_request), EventResponse::Accept) => {
+                friend_request.accept()?
+            }
+            (person_protocol::Event::FriendRequest(friend_request), EventResponse::Reject) => {
+                friend_request.reject()?
+            }
+            (person_protocol::Event::ChatRequest(chat_request), EventResponse::Accept) => {
+                return Ok(Some(
+                    self.chat_pool
+                        .insert(Chat::new(chat_request.accept()?))
+                        .get()?,
+                ));
+            }
+            (person_protocol::Event::ChatRequest(chat_request), EventResponse::Reject) => {
+                chat_request.reject()?
+            }
+            (person_protocol::Event::GroupInvite(group_invite), EventResponse::Accept) => {
+                let group_handle = match self
+                    .subscribe_group(group_invite.ticket().to_string())
+                    .await
+                {
+                    Ok(group_handle) => group_handle,
+                    Err(err) => {
+                        group_invite.reject()?;
+                        return Err(err);
+                    }
+                };
+                group_invite.accept()?;
+                return Ok(Some(group_handle));
+            }
+            (person_protocol::Event::GroupInvite(group_invite), EventResponse::Reject) => {
+                group_invite.reject()?
+            }
+            (person_protocol::Event::RequestExpired(_), _) => eyre::bail!("请求已过期"),
+            (
+                person_protocol::Event::Unfriended(_) | person_protocol::Event::PersonUpdated(_),
+                _,
+            ) => eyre::bail!("该事件无需响应"),
+        }
+        Ok(None)
     }
-    pub async fn request_friend(&self, id: String) -> Result<bool> {
-        Ok(self.person_protocol.request_friend(id.parse()?).await?)
+    pub async fn request_person(&self, id: String) -> Result<Person> {
+        let person = self.person_protocol.request_person(id.parse()?).await?;
+        if let Some(avatar) = &person.avatar
+            && let Some(bytes) = self.person_protocol.avatars().take(&avatar.hash)
+        {
+            let temp_tag = self.storage.store().add_bytes(bytes).temp_tag().await?;
+            self.storage.cache(temp_tag.hash_and_format()).await?;
+        }
+        Ok(person)
     }
-    pub async fn request_chat(&self, id: String) -> Result<Option<usize>> {
+    pub async fn request_profile(&self, id: String, version: u64) -> Result<Option<PersonProfile>> {
         Ok(self
             .person_protocol
-            .request_chat(id.parse()?)
+            .request_profile(id.parse()?, version)
             .await?
-            .map(|v| self.connection_pool.insert(v).get())
-            .transpose()?)
+            .map(Into::into))
+    }
+    pub fn profile(&self) -> PersonProfile {
+        let (person, version) = self.person_protocol.profile();
+        PersonProfile { person, version }
+    }
+    pub async fn update_person(&self, person: LocalPerson) -> Result<()> {
+        let avatar = person.avatar.clone();
+        let person = person.import(self.storage.store()).await?;
+        self.person_protocol.avatars().set_local(avatar);
+        self.person_protocol.set_person(person)
+    }
+    pub async fn fetch_avatar(&self, id: String, hash: String, size: u64) -> Result<Vec<u8>> {
+        avatar::fetch(
+            self.router.endpoint(),
+            &self.storage,
+            id.parse()?,
+            hash.parse()?,
+            size,
+        )
+        .await
+    }
+    pub async fn request_friend(&self, id: String, greeting: Option<String>) -> Result<Outcome> {
+        self.person_protocol
+            .request_friend(id.parse()?, greeting)
+            .await
+    }
+    pub async fn request_chat(&self, id: String) -> Result<Outcome<usize>> {
+        Ok(
+            match self.person_protocol.request_chat(id.parse()?).await? {
+                Outcome::Accepted(connection) => {
+                    Outcome::Accepted(self.chat_pool.insert(Chat::new(connection)).get()?)
+                }
+                Outcome::Rejected => Outcome::Rejected,
+                Outcome::TimedOut => Outcome::TimedOut,
+            },
+        )
+    }
+    pub async fn unfriend(&self, id: String) -> Result<()> {
+        self.person_protocol.unfriend(id.parse()?).await
+    }
+    pub async fn friend_status(&self, id: String) -> Result<bool> {
+        self.person_protocol
+            .request_friend_status(id.parse()?)
+            .await
+    }
+    pub async fn add_friend(&self, id: String) -> Result<()> {
+        self.person_protocol.policy().add_friend(id.parse()?).await
+    }
+    pub fn list_friends(&self) -> Vec<String> {
+        self.person_protocol
+            .policy()
+            .friends()
+            .iter()
+            .map(|v| v.to_string())
+            .collect()
+    }
+    pub async fn send_message(&self, chat_handle: usize, payload: Vec<u8>) -> Result<()> {
+        let chat = self.chat_pool.get(chat_handle).get()?.clone();
+        chat.send(payload).await
+    }
+    pub async fn next_message(&self, chat_handle: usize) -> Result<Option<Vec<u8>>> {
+        let chat = self.chat_pool.get(chat_handle).get()?.clone();
+        chat.next().await
+    }
+    pub async fn close_chat(&self, chat_handle: usize) {
+        if let Some(chat) = self.chat_pool.take(chat_handle) {
+            chat.close().await;
+        }
+    }
+    pub async fn share_file(&self, path: PathBuf) -> Result<SharedBlob> {
+        let store = self.storage.store();
+        let temp_tag = store.add_path(path).temp_tag().await?;
+        SharedBlob::new(store, temp_tag.hash()).await
+    }
+    pub async fn share_bytes(&self, bytes: Vec<u8>) -> Result<SharedBlob> {
+        let store = self.storage.store();
+        let temp_tag = store.add_bytes(bytes).temp_tag().await?;
+        SharedBlob::new(store, temp_tag.hash()).await
+    }
+    pub fn download(&self, id: String, hash: String, dest: Option<PathBuf>) -> Result<usize> {
+        let hash: iroh_blobs::Hash = hash.parse()?;
+        self.transfer_pool
+            .insert(Transfer::spawn(
+                self.router.endpoint().clone(),
+                self.storage.clone(),
+                id.parse()?,
+                GetRequest::blob(hash),
+                dest.map(|dest| (hash, dest)).into_iter().collect(),
+            ))
+            .get()
+    }
+    pub async fn share_directory(&self, path: PathBuf) -> Result<SharedCollection> {
+        collection::share_directory(self.storage.store(), path).await
+    }
+    pub async fn list_collection(&self, id: String, hash: String) -> Result<SharedCollection> {
+        collection::list(
+            self.router.endpoint(),
+            &self.storage,
+            id.parse()?,
+            hash.parse()?,
+        )
+        .await
+    }
+    pub async fn download_collection(
+        &self,
+        id: String,
+        hash: String,
+        entries: Option<Vec<usize>>,
+        dest: Option<PathBuf>,
+    ) -> Result<usize> {
+        let remote_id = id.parse()?;
+        let hash: iroh_blobs::Hash = hash.parse()?;
+        let shared =
+            collection::list(self.router.endpoint(), &self.storage, remote_id, hash).await?;
+        let exports = match &dest {
+            Some(dest) => collection::exports(&shared, entries.as_deref(), dest)?,
+            None => Vec::new(),
+        };
+        let request = collection::request(hash, shared.entries.len(), entries)?;
+        self.transfer_pool
+            .insert(Transfer::spawn(
+                self.router.endpoint().clone(),
+                self.storage.clone(),
+                remote_id,
+                request,
+                exports,
+            ))
+            .get()
+    }
+    pub async fn transfer_next_event(
+        &self,
+        transfer_handle: usize,
+    ) -> Result<Option<TransferEvent>> {
+        let Some(transfer) = self.transfer_pool.get(transfer_handle).map(|v| v.clone()) else {
+            return Ok(None);
+        };
+        let event = transfer.next_event().await;
+        if let Some(TransferEvent::Done { .. } | TransferEvent::Failed { .. }) = event {
+            self.transfer_pool.remove(transfer_handle);
+        }
+        Ok(event)
+    }
+    pub fn cancel_transfer(&self, transfer_handle: usize) {
+        if let Some(transfer) = self.transfer_pool.take(transfer_handle) {
+            transfer.cancel();
+        }
+    }
+    pub async fn read_blob(&self, hash: String) -> Result<Vec<u8>> {
+        let hash: iroh_blobs::Hash = hash.parse()?;
+        let bytes = self.storage.store().get_bytes(hash).await?.to_vec();
+        self.storage.touch(hash).await?;
+        Ok(bytes)
+    }
+    pub async fn tag_blob(&self, hash: String, owner: BlobOwner) -> Result<()> {
+        self.storage
+            .tag(HashAndFormat::raw(hash.parse()?), owner)
+            .await
+    }
+    pub async fn untag_blob(&self, hash: String, owner: BlobOwner) -> Result<()> {
+        self.storage.untag(hash.parse()?, owner).await
+    }
+    pub async fn delete_blob(&self, hash: String) -> Result<()> {
+        self.storage.delete(hash.parse()?).await
+    }
+    pub async fn clear_cache(&self) -> Result<()> {
+        self.storage.clear_cache().await
+    }
+    pub async fn storage_usage(&self) -> Result<StorageUsage> {
+        self.storage.usage().await
+    }
+    pub async fn set_storage_quota(&self, quota: Option<u64>) -> Result<()> {
+        self.person_protocol.policy().set_quota(quota).await?;
+        self.storage.set_quota(quota).await
+    }
+    pub fn protocol_metrics(&self) -> Metrics {
+        self.person_protocol.metrics()
+    }
+    pub async fn block(&self, id: String) -> Result<()> {
+        self.person_protocol.block(id.parse()?).await
+    }
+    pub async fn unblock(&self, id: String) -> Result<()> {
+        self.person_protocol.policy().unblock(id.parse()?).await
+    }
+    pub fn list_blocked(&self) -> Vec<String> {
+        self.person_protocol
+            .policy()
+            .blocked()
+            .iter()
+            .map(|v| v.to_string())
+            .collect()
+    }
+    pub async fn allow(&self, id: String) -> Result<()> {
+        self.person_protocol.policy().allow(id.parse()?).await
+    }
+    pub async fn disallow(&self, id: String) -> Result<()> {
+        self.person_protocol.policy().disallow(id.parse()?).await
+    }
+    pub fn list_allowed(&self) -> Vec<String> {
+        self.person_protocol
+            .policy()
+            .allowed()
+            .iter()
+            .map(|v| v.to_string())
+            .collect()
+    }
+    pub fn allow_only(&self) -> bool {
+        self.person_protocol.policy().allow_only()
+    }
+    pub async fn set_allow_only(&self, allow_only: bool) -> Result<()> {
+        self.person_protocol
+            .policy()
+            .set_allow_only(allow_only)
+            .await
+    }
+    pub fn privacy(&self) -> Privacy {
+        self.person_protocol.policy().privacy()
+    }
+    pub async fn set_privacy(&self, privacy: Privacy) -> Result<()> {
+        self.person_protocol.set_privacy(privacy).await
+    }
+    pub fn create_group_ticket(
+        &self,
+        group_handle: usize,
+        options: TicketOptions,
+    ) -> Result<String> {
+        let group = self.group_pool.get(group_handle).get()?.clone();
+        group.check_inviter()?;
+        let endpoint = self.router.endpoint();
+        let mut bootstrap = vec![endpoint.addr()];
+        for id in &options.bootstrap {
+            bootstrap.push(EndpointAddr::new(id.parse()?));
+        }
+        GroupTicket::new(
+            endpoint.secret_key(),
+            group.id(),
+            Some(group.group()),
+            group.epoch(),
+            bootstrap,
+            options,
+        )?
+        .serialize()
+    }
+    pub async fn create_group(&self, name: String) -> Result<usize> {
+        let group = Group::create(self.group_context(), name).await?;
+        self.group_pool.insert(group).get()
     }
     pub async fn subscribe_group(&self, ticket: String) -> Result<usize> {
-        let ticket = serde_json::from_slice::<Ticket>(&BASE64_STANDARD.decode(ticket)?)?;
-        let group = self
-            .gossip_protocol
-            .subscribe(ticket.id, ticket.bootstrap)
-            .await?
-            .split();
-        Ok(self.group_pool.insert(group).get()?)
+        let parsed = match ParsedTicket::parse(&ticket)? {
+            ParsedTicket::Legacy(legacy) => {
+                let id = self.router.endpoint().id();
+                let group = Group::join(
+                    self.group_context(),
+                    legacy.id,
+                    String::new(),
+                    legacy.bootstrap.into_iter().filter(|v| *v != id).collect(),
+                    None,
+                    legacy.id,
+                    None,
+                )
+                .await?;
+                return self.group_pool.insert(group).get();
+            }
+            ParsedTicket::Group(parsed) => parsed,
+        };
+        parsed.verify()?;
+        let Some(group) = parsed.group else {
+            eyre::bail!("邀请码未绑定群组");
+        };
+        let mut bootstrap = Vec::new();
+        for addr in parsed.bootstrap.iter().cloned() {
+            if addr.id == self.router.endpoint().id() {
+                continue;
+            }
+            bootstrap.push(addr.id);
+            if !addr.is_empty() {
+                self.memory_lookup.add_endpoint_info(addr);
+            }
+        }
+        let group = Group::join(
+            self.group_context(),
+            parsed.id,
+            parsed.name,
+            bootstrap,
+            Some(parsed.inviter),
+            group,
+            Some(ticket),
+        )
+        .await?;
+        self.group_pool.insert(group).get()
+    }
+    pub async fn invite_to_group(&self, friend_id: String, group_handle: usize) -> Result<Outcome> {
+        let id = friend_id.parse()?;
+        if !self.person_protocol.policy().is_friend(&id) {
+            eyre::bail!("对方不是好友");
+        }
+        let group = self.group_pool.get(group_handle).get()?.clone();
+        let ticket = self.create_group_ticket(
+            group_handle,
+            TicketOptions {
+                name: group.name(),
+                expires_at: Some(envelope::now()? + INVITE_EXPIRY.as_millis() as u64),
+                max_uses: Some(1),
+                invitee: Some(friend_id),
+                ..Default::default()
+            },
+        )?;
+        self.person_protocol
+            .request_group_invite(
+                id,
+                GroupInvitation {
+                    ticket,
+                    group_name: group.name(),
+                },
+            )
+            .await
+    }
+    pub async fn group_broadcast(&self, group_handle: usize, payload: Vec<u8>) -> Result<()> {
+        let group = self.group_pool.get(group_handle).get()?.clone();
+        group.broadcast(payload).await
+    }
+    pub async fn group_next_event(&self, group_handle: usize) -> Result<Option<GroupEvent>> {
+        let group = self.group_pool.get(group_handle).get()?.clone();
+        group.next_event().await
+    }
+    pub fn group_membership(&self, group_handle: usize) -> Result<Option<GroupMembership>> {
+        Ok(self.group_pool.get(group_handle).get()?.membership())
+    }
+    pub async fn manage_group(&self, group_handle: usize, action: GroupAction) -> Result<()> {
+        let group = self.group_pool.get(group_handle).get()?.clone();
+        group.manage(action).await
+    }
+    pub fn group_neighbors(&self, group_handle: usize) -> Result<Vec<String>> {
+        Ok(self.group_pool.get(group_handle).get()?.neighbors())
+    }
+    pub fn leave_group(&self, group_handle: usize) {
+        if let Some(group) = self.group_pool.take(group_handle) {
+            self.person_protocol
+                .keyring()
+                .remove(*group.group().as_bytes());
+        }
+    }
+    fn group_context(&self) -> GroupContext {
+        GroupContext {
+            gossip: self.gossip_protocol.clone(),
+            secret_key: self.router.endpoint().secret_key().clone(),
+            person_protocol: self.person_protocol.clone(),
+        }
     }
 }
 
@@ -169,15 +584,12 @@ pub fn get_secret_key_id(secret_key: Vec<u8>) -> Result<String> {
             .to_string(),
     )
 }
-pub fn generate_group_id() -> String {
-    TopicId::from_bytes(rand::random()).to_string()
-}
-pub fn generate_ticket(group_id: String, bootstrap: Vec<String>) -> Result<String> {
-    Ok(BASE64_STANDARD.encode(serde_json::to_vec(&Ticket {
-        id: group_id.parse()?,
-        bootstrap: bootstrap
-            .into_iter()
-            .map(|v| v.parse())
-            .collect::<Result<_, _>>()?,
-    })?))
+pub fn parse_ticket(ticket: String) -> Result<TicketInfo> {
+    match ParsedTicket::parse(&ticket)? {
+        ParsedTicket::Legacy(ticket) => Ok(ticket.info()),
+        ParsedTicket::Group(ticket) => {
+            ticket.verify_signature()?;
+            Ok(ticket.info())
+        }
+    }
 }
@@ -0,0 +1,627 @@
+use std::collections::BTreeMap;
+
+use eyre::{Result, bail, eyre};
+use iroh::{EndpointId, SecretKey, Signature};
+use iroh_gossip::TopicId;
+use person_protocol::MAX_CLOCK_SKEW;
+use serde::{Deserialize, Serialize};
+
+use crate::{envelope, ticket::GroupTicket};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Member,
+    Admin,
+    Owner,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum GroupAction {
+    AddMember { id: String },
+    SetRole { id: String, role: Role },
+    Kick { id: String },
+    Ban { id: String },
+    Unban { id: String },
+    RotateTopic,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupMember {
+    pub id: String,
+    pub role: Role,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupMembership {
+    pub group: String,
+    pub topic: String,
+    pub name: String,
+    pub owner: String,
+    pub version: u64,
+    pub epoch: u64,
+    pub members: Vec<GroupMember>,
+    pub banned: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Signed<T> {
+    pub body: T,
+    pub author: EndpointId,
+    pub signature: Signature,
+}
+impl<T: Serialize> Signed<T> {
+    pub fn new(secret_key: &SecretKey, domain: &[u8], body: T) -> Result<Self> {
+        let signature = secret_key.sign(&Self::message(domain, &body)?);
+        Ok(Self {
+            body,
+            author: secret_key.public(),
+            signature,
+        })
+    }
+    pub fn verify(&self, domain: &[u8]) -> Result<()> {
+        self.author
+            .verify(&Self::message(domain, &self.body)?, &self.signature)
+            .map_err(|_| eyre!("群组消息签名无效"))
+    }
+    fn message(domain: &[u8], body: &T) -> Result<Vec<u8>> {
+        let mut message = domain.to_vec();
+        message.extend_from_slice(&postcard::to_stdvec(body)?);
+        Ok(message)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Membership {
+    pub group: TopicId,
+    pub topic: TopicId,
+    pub name: String,
+    pub owner: EndpointId,
+    pub version: u64,
+    pub members: BTreeMap<EndpointId, Role>,
+    pub banned: BTreeMap<EndpointId, Role>,
+    pub ticket_uses: BTreeMap<[u8; 16], u32>,
+    pub epoch: u64,
+    pub key_hash: [u8; 32],
+}
+impl Membership {
+    pub fn new(owner: EndpointId, name: String, key_hash: [u8; 32]) -> Self {
+        let group = TopicId::from_bytes(rand::random());
+        Self {
+            group,
+            topic: group,
+            name,
+            owner,
+            version: 0,
+            members: BTreeMap::from([(owner, Role::Owner)]),
+            banned: Default::default(),
+            ticket_uses: Default::default(),
+            epoch: 0,
+            key_hash,
+        }
+    }
+    pub fn role(&self, id: &EndpointId) -> Option<Role> {
+        self.members.get(id).copied()
+    }
+    pub fn is_member(&self, id: &EndpointId) -> bool {
+        self.members.contains_key(id)
+    }
+    pub fn is_admin(&self, id: &EndpointId) -> bool {
+        self.role(id) >= Some(Role::Admin)
+    }
+    pub fn apply(&self, author: EndpointId, action: GroupAction) -> Result<Self> {
+        let author_role = self
+            .role(&author)
+            .filter(|v| *v >= Role::Admin)
+            .ok_or_else(|| eyre!("无权管理群组"))?;
+        let mut next = self.clone();
+        next.version += 1;
+        match action {
+            GroupAction::AddMember { id } => {
+                let id = id.parse()?;
+                if next.banned.contains_key(&id) {
+                    bail!("对方已被封禁");
+                }
+                next.members.entry(id).or_insert(Role::Member);
+            }
+            GroupAction::SetRole { id, role } => {
+                let id = id.parse()?;
+                if author_role != Role::Owner {
+                    bail!("只有群主可以设置角色");
+                }
+                if role == Role::Owner || id == self.owner {
+                    bail!("不能变更群主");
+                }
+                if !self.is_member(&id) {
+                    bail!("对方不是群组成员");
+                }
+                next.members.insert(id, role);
+            }
+            GroupAction::Kick { id } => {
+                let id = id.parse()?;
+                if !self.is_member(&id) {
+                    bail!("对方不是群组成员");
+                }
+                next.remove(author_role, id)?;
+            }
+            GroupAction::Ban { id } => {
+                let id = id.parse()?;
+                if self.banned.get(&id) > Some(&author_role) {
+                    bail!("无权变更该封禁");
+                }
+                next.remove(author_role, id)?;
+                next.banned.insert(id, author_role);
+            }
+            GroupAction::Unban { id } => {
+                let id: EndpointId = id.parse()?;
+                if self.banned.get(&id) > Some(&author_role) {
+                    bail!("无权解除该封禁");
+                }
+                next.banned.remove(&id);
+            }
+            GroupAction::RotateTopic => {
+                next.topic = TopicId::from_bytes(rand::random());
+            }
+        }
+        Ok(next)
+    }
+    pub fn admit(
+        &self,
+        author: EndpointId,
+        joiner: EndpointId,
+        ticket: &GroupTicket,
+        timestamp: u64,
+    ) -> Result<Option<Self>> {
+        if self.is_member(&joiner) {
+            return Ok(None);
+        }
+        if envelope::now()?.abs_diff(timestamp) > MAX_CLOCK_SKEW.as_millis() as u64 {
+            bail!("入群请求时间戳无效");
+        }
+        ticket.verify()?;
+        if ticket.id != self.topic
+            || ticket.group.is_some_and(|v| v != self.group)
+            || ticket.epoch != self.epoch
+        {
+            bail!("邀请码已失效");
+        }
+        if ticket.invitee.is_some_and(|v| v != joiner) {
+            bail!("邀请码不属于该用户");
+        }
+        let uses = self.ticket_uses.get(&ticket.nonce).copied().unwrap_or(0);
+        if ticket.max_uses.is_some_and(|v| uses >= v) {
+            bail!("邀请码使用次数已达上限");
+        }
+        let mut next = self.apply(
+            author,
+            GroupAction::AddMember {
+                id: joiner.to_string(),
+            },
+        )?;
+        next.ticket_uses.insert(ticket.nonce, uses + 1);
+        Ok(Some(next))
+    }
+    pub fn removed(&self, next: &Self) -> Vec<EndpointId> {
+        self.members
+            .keys()
+            .filter(|v| !next.is_member(v))
+            .copied()
+            .collect()
+    }
+    pub fn rotate(&mut self, key_hash: [u8; 32]) {
+        self.epoch += 1;
+        self.key_hash = key_hash;
+    }
+    pub fn check(&self) -> Result<()> {
+        if self.role(&self.owner) != Some(Role::Owner)
+            || self.members.values().filter(|v| **v == Role::Owner).count() != 1
+            || self.members.keys().any(|v| self.banned.contains_key(v))
+        {
+            bail!("群组成员文档无效");
+        }
+        Ok(())
+    }
+    pub fn check_transition(&self, next: &Self, author: EndpointId) -> Result<()> {
+        if next.group != self.group || next.owner != self.owner {
+            bail!("群组成员文档不匹配");
+        }
+        if next.version <= self.version {
+            bail!("群组成员文档已过时");
+        }
+        if next.epoch < self.epoch || (next.epoch == self.epoch) != (next.key_hash == self.key_hash)
+        {
+            bail!("群组密钥版本无效");
+        }
+        if next.epoch == self.epoch && !self.removed(next).is_empty() {
+            bail!("移除成员后必须轮换群组密钥");
+        }
+        next.check()?;
+        let author_role = self
+            .role(&author)
+            .filter(|v| *v >= Role::Admin)
+            .ok_or_else(|| eyre!("无权管理群组"))?;
+        if author_role == Role::Admin {
+            for id in self.members.keys().chain(next.members.keys()) {
+                let (old, new) = (self.role(id), next.role(id));
+                if old != new && (old >= Some(Role::Admin) || new >= Some(Role::Admin)) {
+                    bail!("管理员无权变更管理员");
+                }
+            }
+        }
+        for id in self.banned.keys().chain(next.banned.keys()) {
+            let (old, new) = (self.banned.get(id), next.banned.get(id));
+            if old != new && (old > Some(&author_role) || new > Some(&author_role)) {
+                bail!("无权变更该封禁");
+            }
+        }
+        Ok(())
+    }
+    pub fn view(&self) -> GroupMembership {
+        GroupMembership {
+            group: self.group.to_string(),
+            topic: self.topic.to_string(),
+            name: self.name.clone(),
+            owner: self.owner.to_string(),
+            version: self.version,
+            epoch: self.epoch,
+            members: self
+                .members
+                .iter()
+                .map(|(id, role)| GroupMember {
+                    id: id.to_string(),
+                    role: *role,
+                })
+                .collect(),
+            banned: self.banned.keys().map(|v| v.to_string()).collect(),
+        }
+    }
+    fn remove(&mut self, author_role: Role, id: EndpointId) -> Result<()> {
+        if self.role(&id) >= Some(author_role) {
+            bail!("无权移除该成员");
+        }
+        self.members.remove(&id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ticket::TicketOptions;
+
+    fn key() -> SecretKey {
+        SecretKey::generate(&mut rand::rng())
+    }
+
+    fn group(owner: &SecretKey, admin: &SecretKey, member: &SecretKey) -> Membership {
+        let membership = Membership::new(owner.public(), "群组".to_string(), [0; 32]);
+        let membership = membership
+            .apply(
+                owner.public(),
+                GroupAction::AddMember {
+                    id: admin.public().to_string(),
+                },
+            )
+            .unwrap()
+            .apply(
+                owner.public(),
+                GroupAction::SetRole {
+                    id: admin.public().to_string(),
+                    role: Role::Admin,
+                },
+            )
+            .unwrap()
+            .apply(
+                owner.public(),
+                GroupAction::AddMember {
+                    id: member.public().to_string(),
+                },
+            )
+            .unwrap();
+        membership.check().unwrap();
+        membership
+    }
+
+    fn ticket(inviter: &SecretKey, membership: &Membership, max_uses: Option<u32>) -> GroupTicket {
+        GroupTicket::new(
+            inviter,
+            membership.topic,
+            Some(membership.group),
+            membership.epoch,
+            Vec::new(),
+            TicketOptions {
+                max_uses,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn only_owner_sets_roles() {
+        let (owner, admin, member) = (key(), key(), key());
+        let membership = group(&owner, &admin, &member);
+        assert_eq!(membership.role(&admin.public()), Some(Role::Admin));
+        let set_role = |id: &SecretKey, role| GroupAction::SetRole {
+            id: id.public().to_string(),
+            role,
+        };
+        assert!(
+            membership
+                .apply(admin.public(), set_role(&member, Role::Admin))
+                .is_err()
+        );
+        assert!(
+            membership
+                .apply(owner.public(), set_role(&member, Role::Owner))
+                .is_err()
+        );
+        assert!(
+            membership
+                .apply(member.public(), set_role(&member, Role::Admin))
+                .is_err()
+        );
+        let next = membership
+            .apply(owner.public(), set_role(&admin, Role::Member))
+            .unwrap();
+        assert_eq!(next.role(&admin.public()), Some(Role::Member));
+        membership.check_transition(&next, owner.public()).unwrap();
+        assert!(membership.check_transition(&next, admin.public()).is_err());
+    }
+
+    #[test]
+    fn kick_respects_roles() {
+        let (owner, admin, member) = (key(), key(), key());
+        let membership = group(&owner, &admin, &member);
+        let kick = |id: &SecretKey| GroupAction::Kick {
+            id: id.public().to_string(),
+        };
+        assert!(membership.apply(admin.public(), kick(&owner)).is_err());
+        assert!(membership.apply(member.public(), kick(&admin)).is_err());
+        assert!(membership.apply(admin.public(), kick(&key())).is_err());
+        let next = membership.apply(admin.public(), kick(&member)).unwrap();
+        assert!(!next.is_member(&member.public()));
+        assert!(!next.banned.contains_key(&member.public()));
+        assert_eq!(membership.removed(&next), vec![member.public()]);
+        let next = membership.apply(owner.public(), kick(&admin)).unwrap();
+        assert!(!next.is_member(&admin.public()));
+    }
+
+    #[test]
+    fn ban_blocks_readding() {
+        let (owner, admin, member) = (key(), key(), key());
+        let membership = group(&owner, &admin, &member);
+        let next = membership
+            .apply(
+                admin.public(),
+                GroupAction::Ban {
+                    id: member.public().to_string(),
+                },
+            )
+            .unwrap();
+        assert!(!next.is_member(&member.public()));
+        assert!(next.banned.contains_key(&member.public()));
+        let add = GroupAction::AddMember {
+            id: member.public().to_string(),
+        };
+        assert!(next.apply(owner.public(), add.clone()).is_err());
+        let next = next
+            .apply(
+                owner.public(),
+                GroupAction::Unban {
+                    id: member.public().to_string(),
+                },
+            )
+            .unwrap();
+        assert!(next.apply(owner.public(), add).is_ok());
+    }
+
+    #[test]
+    fn removal_requires_key_rotation() {
+        let (owner, admin, member) = (key(), key(), key());
+        let membership = group(&owner, &admin, &member);
+        let mut next = membership
+            .apply(
+                admin.public(),
+                GroupAction::Kick {
+                    id: member.public().to_string(),
+                },
+            )
+            .unwrap();
+        assert!(membership.check_transition(&next, admin.public()).is_err());
+        next.rotate([1; 32]);
+        membership.check_transition(&next, admin.public()).unwrap();
+        let mut stale = next.clone();
+        stale.version = membership.version;
+        assert!(membership.check_transition(&stale, admin.public()).is_err());
+        let mut reused = next.clone();
+        reused.key_hash = membership.key_hash;
+        assert!(
+            membership
+                .check_transition(&reused, admin.public())
+                .is_err()
+        );
+        let rotated = membership
+            .apply(admin.public(), GroupAction::RotateTopic)
+            .unwrap();
+        membership
+            .check_transition(&rotated, admin.public())
+            .unwrap();
+    }
+
+    #[test]
+    fn admit_checks_inviter_and_ticket() {
+        let (owner, admin, member) = (key(), key(), key());
+        let membership = group(&owner, &admin, &member);
+        let now = envelope::now().unwrap();
+        let joiner = key().public();
+        let next = membership
+            .admit(
+                admin.public(),
+                joiner,
+                &ticket(&admin, &membership, None),
+                now,
+            )
+            .unwrap()
+            .unwrap();
+        assert!(next.is_member(&joiner));
+        membership.check_transition(&next, admin.public()).unwrap();
+        assert!(
+            membership
+                .admit(
+                    member.public(),
+                    joiner,
+                    &ticket(&member, &membership, None),
+                    now
+                )
+                .is_err()
+        );
+        assert!(
+            membership
+                .admit(
+                    admin.public(),
+                    member.public(),
+                    &ticket(&admin, &membership, None),
+                    now
+                )
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            membership
+                .admit(
+                    admin.public(),
+                    joiner,
+                    &ticket(&admin, &membership, None),
+                    now - 2 * MAX_CLOCK_SKEW.as_millis() as u64
+                )
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn admit_enforces_max_uses() {
+        let (owner, admin, member) = (key(), key(), key());
+        let membership = group(&owner, &admin, &member);
+        let now = envelope::now().unwrap();
+        let ticket = ticket(&admin, &membership, Some(1));
+        let next = membership
+            .admit(admin.public(), key().public(), &ticket, now)
+            .unwrap()
+            .unwrap();
+        assert!(
+            next.admit(admin.public(), key().public(), &ticket, now)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn admit_rejects_wrong_topic() {
+        let (owner, admin, member) = (key(), key(), key());
+        let membership = group(&owner, &admin, &member);
+        let now = envelope::now().unwrap();
+        let ticket = ticket(&admin, &membership, None);
+        let rotated = membership
+            .apply(admin.public(), GroupAction::RotateTopic)
+            .unwrap();
+        assert!(
+            rotated
+                .admit(admin.public(), key().public(), &ticket, now)
+                .is_err()
+        );
+        let other = Membership::new(owner.public(), "群组".to_string(), [0; 32]);
+        let mut forged = ticket.clone();
+        forged.id = other.topic;
+        assert!(
+            other
+                .admit(owner.public(), key().public(), &forged, now)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn admit_rejects_other_invitee() {
+        let (owner, admin, member) = (key(), key(), key());
+        let membership = group(&owner, &admin, &member);
+        let now = envelope::now().unwrap();
+        let invitee = key().public();
+        let ticket = GroupTicket::new(
+            &admin,
+            membership.topic,
+            Some(membership.group),
+            membership.epoch,
+            Vec::new(),
+            TicketOptions {
+                invitee: Some(invitee.to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(
+            membership
+                .admit(admin.public(), key().public(), &ticket, now)
+                .is_err()
+        );
+        let next = membership
+            .admit(admin.public(), invitee, &ticket, now)
+            .unwrap()
+            .unwrap();
+        assert!(next.is_member(&invitee));
+    }
+
+    #[test]
+    fn kick_invalidates_outstanding_tickets() {
+        let (owner, admin, member) = (key(), key(), key());
+        let membership = group(&owner, &admin, &member);
+        let now = envelope::now().unwrap();
+        let ticket = ticket(&admin, &membership, None);
+        assert!(
+            membership
+                .admit(admin.public(), key().public(), &ticket, now)
+                .unwrap()
+                .is_some()
+        );
+        let mut next = membership
+            .apply(
+                admin.public(),
+                GroupAction::Kick {
+                    id: member.public().to_string(),
+                },
+            )
+            .unwrap();
+        next.rotate([1; 32]);
+        assert!(
+            next.admit(admin.public(), key().public(), &ticket, now)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn admin_cannot_lift_owner_ban() {
+        let (owner, admin, member) = (key(), key(), key());
+        let membership = group(&owner, &admin, &member);
+        let banned = membership
+            .apply(
+                owner.public(),
+                GroupAction::Ban {
+                    id: member.public().to_string(),
+                },
+            )
+            .unwrap();
+        let unban = GroupAction::Unban {
+            id: member.public().to_string(),
+        };
+        assert!(banned.apply(admin.public(), unban.clone()).is_err());
+        let ban = GroupAction::Ban {
+            id: member.public().to_string(),
+        };
+        assert!(banned.apply(admin.public(), ban).is_err());
+        let mut forged = banned.clone();
+        forged.version += 1;
+        forged.banned.remove(&member.public());
+        assert!(banned.check_transition(&forged, admin.public()).is_err());
+        let next = banned.apply(owner.public(), unban).unwrap();
+        assert!(!next.banned.contains_key(&member.public()));
+        banned.check_transition(&next, owner.public()).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+use std::{path::PathBuf, sync::Arc};
+
+use eyre::Result;
+use person_protocol::policy::{MemoryPolicyStore, PolicyStore};
+use serde::{Deserialize, Serialize};
+
+use crate::builder::StoreOption;
+
+const POLICY_FILE: &str = "policy.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub enum PolicyOption {
+    #[default]
+    Store,
+    Memory,
+    Fs(PathBuf),
+}
+impl PolicyOption {
+    pub(crate) fn into_store(self, store: &StoreOption) -> Result<Arc<dyn PolicyStore>> {
+        Ok(match (self, store) {
+            (PolicyOption::Store, StoreOption::Memory) | (PolicyOption::Memory, _) => {
+                Arc::new(MemoryPolicyStore)
+            }
+            #[cfg(not(target_family = "wasm"))]
+            (PolicyOption::Store, StoreOption::Fs(path)) => {
+                Arc::new(FsPolicyStore(path.join(POLICY_FILE)))
+            }
+            #[cfg(not(target_family = "wasm"))]
+            (PolicyOption::Fs(path), _) => Arc::new(FsPolicyStore(path)),
+            #[cfg(target_family = "wasm")]
+            (PolicyOption::Store, StoreOption::Fs(_)) | (PolicyOption::Fs(_), _) => {
+                eyre::bail!("当前平台不支持文件存储")
+            }
+        })
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+#[derive(Debug)]
+pub struct FsPolicyStore(pub PathBuf);
+#[cfg(not(target_family = "wasm"))]
+impl PolicyStore for FsPolicyStore {
+    fn load(&self) -> Result<person_protocol::policy::PolicyList> {
+        if !self.0.exists() {
+            return Ok(Default::default());
+        }
+        Ok(serde_json::from_slice(&std::fs::read(&self.0)?)?)
+    }
+    fn save(
+        &self,
+        list: person_protocol::policy::PolicyList,
+    ) -> n0_future::boxed::BoxFuture<Result<()>> {
+        let path = self.0.clone();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                let temp = path.with_extension("json.tmp");
+                std::fs::write(&temp, serde_json::to_vec(&list)?)?;
+                std::fs::File::open(&temp)?.sync_all()?;
+                std::fs::rename(&temp, &path)?;
+                eyre::Ok(())
+            })
+            .await?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+    use person_protocol::policy::PolicyList;
+
+    use super::*;
+    use crate::{AddressLookupOption, Endpoint, LocalPerson, RelayOption};
+
+    #[derive(Debug, Clone, Default)]
+    struct SharedPolicyStore(Arc<parking_lot::Mutex<PolicyList>>);
+    impl PolicyStore for SharedPolicyStore {
+        fn load(&self) -> Result<PolicyList> {
+            Ok(self.0.lock().clone())
+        }
+        fn save(&self, list: PolicyList) -> n0_future::boxed::BoxFuture<Result<()>> {
+            *self.0.lock() = list;
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    async fn endpoint(secret_key: &SecretKey, policy_store: SharedPolicyStore) -> Endpoint {
+        Endpoint::builder(
+            secret_key.to_bytes().to_vec(),
+            LocalPerson {
+                name: "测试".to_string(),
+                avatar: None,
+                bio: String::new(),
+            },
+        )
+        .relay(RelayOption::Disabled)
+        .address_lookup(AddressLookupOption::disabled())
+        .store(StoreOption::Memory)
+        .policy_store(policy_store)
+        .bind()
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn restores_storage_quota() {
+        let secret_key = SecretKey::generate(&mut rand::rng());
+        let policy_store = SharedPolicyStore::default();
+        let first = endpoint(&secret_key, policy_store.clone()).await;
+        assert_eq!(first.storage_usage().await.unwrap().quota, None);
+        first.set_storage_quota(Some(1024)).await.unwrap();
+        first.close().await.unwrap();
+
+        let second = endpoint(&secret_key, policy_store).await;
+        assert_eq!(second.storage_usage().await.unwrap().quota, Some(1024));
+        second.close().await.unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+use chacha20poly1305::{
+    KeyInit, XChaCha20Poly1305,
+    aead::{Aead, Payload},
+};
+use eyre::{Result, eyre};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Sealed {
+    pub epoch: u64,
+    nonce: [u8; 24],
+    ciphertext: Vec<u8>,
+}
+impl Sealed {
+    pub fn seal(key: &[u8; 32], aad: &[u8], epoch: u64, plaintext: &[u8]) -> Result<Self> {
+        let nonce = rand::random::<[u8; 24]>();
+        let ciphertext = XChaCha20Poly1305::new(key.into())
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: plaintext,
+                    aad: &Self::aad(aad, epoch),
+                },
+            )
+            .map_err(|_| eyre!("群组消息加密失败"))?;
+        Ok(Self {
+            epoch,
+            nonce,
+            ciphertext,
+        })
+    }
+    pub fn open(&self, key: &[u8; 32], aad: &[u8]) -> Result<Vec<u8>> {
+        XChaCha20Poly1305::new(key.into())
+            .decrypt(
+                &self.nonce.into(),
+                Payload {
+                    msg: &self.ciphertext,
+                    aad: &Self::aad(aad, self.epoch),
+                },
+            )
+            .map_err(|_| eyre!("群组消息解密失败"))
+    }
+    fn aad(aad: &[u8], epoch: u64) -> Vec<u8> {
+        let mut bytes = aad.to_vec();
+        bytes.extend_from_slice(&epoch.to_be_bytes());
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_with_matching_key_and_aad() {
+        let key = rand::random();
+        let sealed = Sealed::seal(&key, b"group", 1, b"hello").unwrap();
+        assert_eq!(sealed.open(&key, b"group").unwrap(), b"hello");
+    }
+
+    #[test]
+    fn rejects_wrong_key() {
+        let sealed = Sealed::seal(&rand::random(), b"group", 1, b"hello").unwrap();
+        assert!(sealed.open(&rand::random(), b"group").is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_epoch() {
+        let key = rand::random();
+        let mut sealed = Sealed::seal(&key, b"group", 1, b"hello").unwrap();
+        sealed.epoch = 2;
+        assert!(sealed.open(&key, b"group").is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_aad() {
+        let key = rand::random();
+        let sealed = Sealed::seal(&key, b"group", 1, b"hello").unwrap();
+        assert!(sealed.open(&key, b"other").is_err());
+    }
+}
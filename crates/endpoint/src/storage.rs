@@ -0,0 +1,374 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use eyre::Result;
+use futures::StreamExt;
+use iroh_blobs::{
+    BlobFormat, Hash, HashAndFormat,
+    api::{Store, proto::TagInfo},
+    hashseq::HashSeq,
+    store::GcConfig,
+};
+use n0_future::{task::AbortOnDropHandle, time::SystemTime};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+const GC_INTERVAL: Duration = Duration::from_secs(60);
+const EVICT_INTERVAL: Duration = Duration::from_secs(10);
+const CACHE_PREFIX: &str = "cache-";
+const DOWNLOAD_PREFIX: &str = "download-";
+const DOWNLOAD_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub(crate) fn gc_config() -> GcConfig {
+    GcConfig {
+        interval: GC_INTERVAL,
+        add_protected: None,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlobOwner {
+    Avatar,
+    ChatAttachment,
+    GroupHistory,
+    Share,
+    Download,
+    Cache,
+}
+impl BlobOwner {
+    const ALL: [BlobOwner; 6] = [
+        BlobOwner::Avatar,
+        BlobOwner::ChatAttachment,
+        BlobOwner::GroupHistory,
+        BlobOwner::Share,
+        BlobOwner::Download,
+        BlobOwner::Cache,
+    ];
+    fn prefix(self) -> &'static str {
+        match self {
+            BlobOwner::Avatar => "avatar",
+            BlobOwner::ChatAttachment => "chat",
+            BlobOwner::GroupHistory => "group",
+            BlobOwner::Share => "share",
+            BlobOwner::Download => "download",
+            BlobOwner::Cache => "cache",
+        }
+    }
+    pub(crate) fn tag(self, hash: Hash) -> String {
+        format!("{}-{}", self.prefix(), hash)
+    }
+    fn is_stamped(self) -> bool {
+        matches!(self, BlobOwner::Download | BlobOwner::Cache)
+    }
+    fn from_tag(name: &[u8]) -> Option<Self> {
+        let prefix = std::str::from_utf8(name).ok()?.split('-').next()?;
+        Self::ALL.into_iter().find(|v| v.prefix() == prefix)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StorageUsage {
+    pub total: u64,
+    pub avatar: u64,
+    pub chat_attachment: u64,
+    pub group_history: u64,
+    pub share: u64,
+    pub download: u64,
+    pub cache: u64,
+    pub untagged: u64,
+    pub quota: Option<u64>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Storage {
+    store: Store,
+    quota: Arc<Mutex<Option<u64>>>,
+    dirty: Arc<AtomicBool>,
+    evictor: Option<Arc<AbortOnDropHandle<()>>>,
+}
+impl Storage {
+    pub fn new(store: Store, quota: Option<u64>) -> Self {
+        let mut storage = Self {
+            store,
+            quota: Arc::new(Mutex::new(quota)),
+            dirty: Arc::new(AtomicBool::new(true)),
+            evictor: None,
+        };
+        let task = n0_future::task::spawn({
+            let storage = storage.clone();
+            async move {
+                loop {
+                    n0_future::time::sleep(EVICT_INTERVAL).await;
+                    if let Err(err) = storage.expire().await {
+                        log::warn!("清理过期下载失败：{}", err);
+                    }
+                    if storage.dirty.swap(false, Ordering::Relaxed)
+                        && let Err(err) = storage.evict().await
+                    {
+                        log::warn!("清理缓存失败：{}", err);
+                    }
+                }
+            }
+        });
+        storage.evictor = Some(Arc::new(AbortOnDropHandle::new(task)));
+        storage
+    }
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+    pub async fn set_quota(&self, quota: Option<u64>) -> Result<()> {
+        *self.quota.lock() = quota;
+        self.evict().await
+    }
+    pub async fn tag(&self, content: HashAndFormat, owner: BlobOwner) -> Result<()> {
+        if owner.is_stamped() {
+            return self.stamp(owner, content).await;
+        }
+        self.store
+            .tags()
+            .set(owner.tag(content.hash), content)
+            .await?;
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+    pub async fn untag(&self, hash: Hash, owner: BlobOwner) -> Result<()> {
+        if owner.is_stamped() {
+            return self.unstamp(owner, hash).await;
+        }
+        self.store.tags().delete(owner.tag(hash)).await?;
+        Ok(())
+    }
+    pub async fn cache(&self, content: HashAndFormat) -> Result<()> {
+        self.stamp(BlobOwner::Cache, content).await
+    }
+    pub async fn touch(&self, hash: Hash) -> Result<()> {
+        if let Some(tag) = self
+            .cache_tags()
+            .await?
+            .into_iter()
+            .find(|v| v.hash == hash)
+        {
+            self.cache(tag.hash_and_format()).await?;
+        }
+        Ok(())
+    }
+    pub async fn clear_cache(&self) -> Result<()> {
+        self.store.tags().delete_prefix(CACHE_PREFIX).await?;
+        Ok(())
+    }
+    pub async fn delete(&self, hash: Hash) -> Result<()> {
+        for tag in self.tags("").await? {
+            if tag.hash == hash {
+                self.store.tags().delete(tag.name).await?;
+            }
+        }
+        Ok(())
+    }
+    pub async fn usage(&self) -> Result<StorageUsage> {
+        let mut sizes = HashMap::new();
+        for hash in self.store.list().hashes().await? {
+            sizes.insert(hash, self.size(hash).await?);
+        }
+        let mut owners: HashMap<_, HashSet<_>> = HashMap::new();
+        let mut tagged = HashSet::new();
+        for tag in self.tags("").await? {
+            let hashes = self.reachable(tag.hash_and_format()).await?;
+            tagged.extend(hashes.iter().copied());
+            if let Some(owner) = BlobOwner::from_tag(&tag.name.0) {
+                owners.entry(owner).or_default().extend(hashes);
+            }
+        }
+        let sum = |hashes: Option<&HashSet<Hash>>| {
+            hashes
+                .into_iter()
+                .flatten()
+                .filter_map(|v| sizes.get(v))
+                .sum::<u64>()
+        };
+        let total = sizes.values().sum::<u64>();
+        Ok(StorageUsage {
+            total,
+            avatar: sum(owners.get(&BlobOwner::Avatar)),
+            chat_attachment: sum(owners.get(&BlobOwner::ChatAttachment)),
+            group_history: sum(owners.get(&BlobOwner::GroupHistory)),
+            share: sum(owners.get(&BlobOwner::Share)),
+            download: sum(owners.get(&BlobOwner::Download)),
+            cache: sum(owners.get(&BlobOwner::Cache)),
+            untagged: total.saturating_sub(sum(Some(&tagged))),
+            quota: *self.quota.lock(),
+        })
+    }
+    async fn stamp(&self, owner: BlobOwner, content: HashAndFormat) -> Result<()> {
+        self.unstamp(owner, content.hash).await?;
+        self.store
+            .tags()
+            .set(
+                format!("{}-{:016x}-{}", owner.prefix(), timestamp()?, content.hash),
+                content,
+            )
+            .await?;
+        self.dirty.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+    async fn unstamp(&self, owner: BlobOwner, hash: Hash) -> Result<()> {
+        for tag in self.tags(&format!("{}-", owner.prefix())).await? {
+            if tag.hash == hash {
+                self.store.tags().delete(tag.name).await?;
+            }
+        }
+        Ok(())
+    }
+    async fn expire(&self) -> Result<()> {
+        let now = timestamp()?;
+        for tag in self.tags(DOWNLOAD_PREFIX).await? {
+            if std::str::from_utf8(&tag.name.0)
+                .ok()
+                .and_then(|v| v.strip_prefix(DOWNLOAD_PREFIX)?.split('-').next())
+                .and_then(|v| u128::from_str_radix(v, 16).ok())
+                .is_none_or(|v| now.saturating_sub(v) > DOWNLOAD_EXPIRY.as_millis())
+            {
+                self.store.tags().delete(tag.name).await?;
+            }
+        }
+        Ok(())
+    }
+    async fn evict(&self) -> Result<()> {
+        let Some(quota) = *self.quota.lock() else {
+            return Ok(());
+        };
+        let mut seen = HashSet::new();
+        let mut used = 0;
+        for tag in self.tags("").await? {
+            if BlobOwner::from_tag(&tag.name.0) == Some(BlobOwner::Cache) {
+                continue;
+            }
+            for hash in self.reachable(tag.hash_and_format()).await? {
+                if seen.insert(hash) {
+                    used += self.size(hash).await?;
+                }
+            }
+        }
+        let mut caches = Vec::new();
+        for tag in self.cache_tags().await?.into_iter().rev() {
+            let mut size = 0;
+            for hash in self.reachable(tag.hash_and_format()).await? {
+                if seen.insert(hash) {
+                    size += self.size(hash).await?;
+                }
+            }
+            used += size;
+            caches.push((tag, size));
+        }
+        for (tag, size) in caches.into_iter().rev() {
+            if used <= quota {
+                break;
+            }
+            self.store.tags().delete(tag.name).await?;
+            used -= size;
+        }
+        Ok(())
+    }
+    async fn cache_tags(&self) -> Result<Vec<TagInfo>> {
+        self.tags(CACHE_PREFIX).await
+    }
+    async fn tags(&self, prefix: &str) -> Result<Vec<TagInfo>> {
+        Ok(self
+            .store
+            .tags()
+            .list_prefix(prefix)
+            .await?
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<_, _>>()?)
+    }
+    async fn reachable(&self, content: HashAndFormat) -> Result<Vec<Hash>> {
+        let mut hashes = vec![content.hash];
+        if content.format == BlobFormat::HashSeq
+            && let Ok(bytes) = self.store.get_bytes(content.hash).await
+            && let Ok(hash_seq) = HashSeq::try_from(bytes)
+        {
+            hashes.extend(hash_seq);
+        }
+        Ok(hashes)
+    }
+    async fn size(&self, hash: Hash) -> Result<u64> {
+        Ok(self.store.remote().local(hash).await?.local_bytes())
+    }
+}
+
+fn timestamp() -> Result<u128> {
+    Ok(SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh_blobs::store::mem::MemStore;
+
+    use super::*;
+
+    async fn add(storage: &Storage, bytes: &[u8]) -> HashAndFormat {
+        let content = storage
+            .store()
+            .add_bytes(bytes.to_vec())
+            .temp_tag()
+            .await
+            .unwrap()
+            .hash_and_format();
+        n0_future::time::sleep(Duration::from_millis(5)).await;
+        content
+    }
+
+    #[tokio::test]
+    async fn evicts_oldest_cache_until_under_quota() {
+        let storage = Storage::new(MemStore::new().into(), None);
+        let shared = add(&storage, &[0; 100]).await;
+        storage.tag(shared, BlobOwner::Share).await.unwrap();
+        let mut caches = Vec::new();
+        for i in 1..=3 {
+            let content = add(&storage, &[i; 100]).await;
+            storage.cache(content).await.unwrap();
+            n0_future::time::sleep(Duration::from_millis(5)).await;
+            caches.push(content.hash);
+        }
+
+        storage.set_quota(Some(250)).await.unwrap();
+        let cached = storage
+            .cache_tags()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|v| v.hash)
+            .collect::<Vec<_>>();
+        assert_eq!(cached, [caches[2]]);
+        assert!(
+            storage
+                .store()
+                .tags()
+                .get(BlobOwner::Share.tag(shared.hash))
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        storage.set_quota(Some(50)).await.unwrap();
+        assert!(storage.cache_tags().await.unwrap().is_empty());
+        assert!(
+            storage
+                .store()
+                .tags()
+                .get(BlobOwner::Share.tag(shared.hash))
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use base64::{Engine, prelude::BASE64_STANDARD};
+use data_encoding::BASE32_NOPAD;
+use eyre::{Result, bail, eyre};
+use iroh::{EndpointAddr, EndpointId, SecretKey, Signature};
+use iroh_gossip::TopicId;
+use n0_future::time::SystemTime;
+use serde::{Deserialize, Serialize};
+
+const TICKET_KIND: &str = "dp2p";
+const TICKET_VERSION: u8 = 2;
+
+#[derive(Serialize, Deserialize)]
+pub struct Ticket {
+    pub id: TopicId,
+    pub bootstrap: Vec<EndpointId>,
+}
+impl Ticket {
+    pub fn serialize(&self) -> Result<String> {
+        Ok(BASE64_STANDARD.encode(serde_json::to_vec(self)?))
+    }
+    pub fn deserialize(ticket: &str) -> Result<Self> {
+        let bytes = BASE64_STANDARD
+            .decode(ticket)
+            .map_err(|_| eyre!("邀请码格式无效"))?;
+        serde_json::from_slice(&bytes).map_err(|_| eyre!("邀请码格式无效"))
+    }
+    pub fn info(&self) -> TicketInfo {
+        TicketInfo {
+            id: self.id.to_string(),
+            name: String::new(),
+            inviter: None,
+            invitee: None,
+            bootstrap: self.bootstrap.iter().map(|v| v.to_string()).collect(),
+            expires_at: None,
+            max_uses: None,
+            expired: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TicketOptions {
+    pub name: String,
+    pub bootstrap: Vec<String>,
+    pub expires_at: Option<u64>,
+    pub max_uses: Option<u32>,
+    pub invitee: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TicketInfo {
+    pub id: String,
+    pub name: String,
+    pub inviter: Option<String>,
+    pub invitee: Option<String>,
+    pub bootstrap: Vec<String>,
+    pub expires_at: Option<u64>,
+    pub max_uses: Option<u32>,
+    pub expired: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupTicket {
+    pub id: TopicId,
+    pub group: Option<TopicId>,
+    pub epoch: u64,
+    pub name: String,
+    pub bootstrap: Vec<EndpointAddr>,
+    pub expires_at: Option<u64>,
+    pub max_uses: Option<u32>,
+    pub nonce: [u8; 16],
+    pub inviter: EndpointId,
+    pub invitee: Option<EndpointId>,
+    pub signature: Signature,
+}
+
+impl GroupTicket {
+    pub fn new(
+        secret_key: &SecretKey,
+        id: TopicId,
+        group: Option<TopicId>,
+        epoch: u64,
+        bootstrap: Vec<EndpointAddr>,
+        options: TicketOptions,
+    ) -> Result<Self> {
+        let mut ticket = Self {
+            id,
+            group,
+            epoch,
+            name: options.name,
+            bootstrap,
+            expires_at: options.expires_at,
+            max_uses: options.max_uses,
+            nonce: rand::random(),
+            inviter: secret_key.public(),
+            invitee: options.invitee.as_deref().map(str::parse).transpose()?,
+            signature: Signature::from_bytes(&[0; Signature::LENGTH]),
+        };
+        ticket.signature = secret_key.sign(&ticket.message()?);
+        Ok(ticket)
+    }
+    pub fn verify(&self) -> Result<()> {
+        self.verify_signature()?;
+        if self.is_expired() {
+            bail!("邀请码已过期");
+        }
+        Ok(())
+    }
+    pub fn verify_signature(&self) -> Result<()> {
+        self.inviter
+            .verify(&self.message()?, &self.signature)
+            .map_err(|_| eyre!("邀请码签名无效"))
+    }
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .is_ok_and(|v| v.as_millis() > expires_at as u128)
+        })
+    }
+    fn message(&self) -> Result<Vec<u8>> {
+        let mut message = b"dp2p/group-ticket/2".to_vec();
+        message.extend_from_slice(&postcard::to_stdvec(&(
+            self.id,
+            self.group,
+            self.epoch,
+            &self.name,
+            &self.bootstrap,
+            self.expires_at,
+            self.max_uses,
+            self.nonce,
+            self.invitee,
+        ))?);
+        Ok(message)
+    }
+    pub fn info(&self) -> TicketInfo {
+        TicketInfo {
+            id: self.id.to_string(),
+            name: self.name.clone(),
+            inviter: Some(self.inviter.to_string()),
+            invitee: self.invitee.map(|v| v.to_string()),
+            bootstrap: self.bootstrap.iter().map(|v| v.id.to_string()).collect(),
+            expires_at: self.expires_at,
+            max_uses: self.max_uses,
+            expired: self.is_expired(),
+        }
+    }
+    pub fn serialize(&self) -> Result<String> {
+        let mut bytes = vec![TICKET_VERSION];
+        bytes.extend(postcard::to_stdvec(self)?);
+        let mut ticket = TICKET_KIND.to_string();
+        BASE32_NOPAD.encode_append(&bytes, &mut ticket);
+        Ok(ticket.to_ascii_lowercase())
+    }
+    pub fn deserialize(ticket: &str) -> Result<Self> {
+        let Some(ticket) = ticket.strip_prefix(TICKET_KIND) else {
+            bail!("邀请码格式无效");
+        };
+        let bytes = BASE32_NOPAD
+            .decode(ticket.to_ascii_uppercase().as_bytes())
+            .map_err(|_| eyre!("邀请码格式无效"))?;
+        match bytes.split_first() {
+            Some((&TICKET_VERSION, bytes)) => {
+                postcard::from_bytes(bytes).map_err(|_| eyre!("邀请码格式无效"))
+            }
+            Some(_) => bail!("不支持的邀请码版本"),
+            None => bail!("邀请码格式无效"),
+        }
+    }
+}
+
+pub(crate) enum ParsedTicket {
+    Legacy(Ticket),
+    Group(Box<GroupTicket>),
+}
+impl ParsedTicket {
+    pub fn parse(ticket: &str) -> Result<Self> {
+        if ticket.starts_with(TICKET_KIND) {
+            Ok(ParsedTicket::Group(Box::new(GroupTicket::deserialize(
+                ticket,
+            )?)))
+        } else {
+            Ok(ParsedTicket::Legacy(Ticket::deserialize(ticket)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticket() -> GroupTicket {
+        GroupTicket::new(
+            &SecretKey::generate(&mut rand::rng()),
+            TopicId::from_bytes([1; 32]),
+            Some(TopicId::from_bytes([2; 32])),
+            3,
+            vec![EndpointAddr::new(
+                SecretKey::generate(&mut rand::rng()).public(),
+            )],
+            TicketOptions {
+                name: "群组".to_string(),
+                expires_at: Some(u64::MAX),
+                max_uses: Some(3),
+                invitee: Some(SecretKey::generate(&mut rand::rng()).public().to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trips_serialized_ticket() {
+        let ticket = ticket();
+        let parsed = GroupTicket::deserialize(&ticket.serialize().unwrap()).unwrap();
+        parsed.verify().unwrap();
+        assert_eq!(parsed.id, ticket.id);
+        assert_eq!(parsed.group, ticket.group);
+        assert_eq!(parsed.epoch, ticket.epoch);
+        assert_eq!(parsed.name, ticket.name);
+        assert_eq!(parsed.bootstrap, ticket.bootstrap);
+        assert_eq!(parsed.expires_at, ticket.expires_at);
+        assert_eq!(parsed.max_uses, ticket.max_uses);
+        assert_eq!(parsed.nonce, ticket.nonce);
+        assert_eq!(parsed.inviter, ticket.inviter);
+        assert_eq!(parsed.invitee, ticket.invitee);
+    }
+
+    #[test]
+    fn rejects_tampered_ticket() {
+        let mut tampered = ticket();
+        tampered.max_uses = None;
+        let parsed = GroupTicket::deserialize(&tampered.serialize().unwrap()).unwrap();
+        assert!(parsed.verify_signature().is_err());
+
+        let mut tampered = ticket();
+        tampered.group = None;
+        assert!(tampered.verify_signature().is_err());
+
+        let mut tampered = ticket();
+        tampered.invitee = None;
+        assert!(tampered.verify_signature().is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_ticket() {
+        let serialized = ticket().serialize().unwrap();
+        for malformed in [
+            format!("{}!", serialized),
+            serialized[..serialized.len() - 8].to_string(),
+            TICKET_KIND.to_string(),
+        ] {
+            let err = GroupTicket::deserialize(&malformed).err().unwrap();
+            assert_eq!(err.to_string(), "邀请码格式无效");
+        }
+        let mut bytes = vec![TICKET_VERSION + 1];
+        bytes.extend(postcard::to_stdvec(&ticket()).unwrap());
+        let mut unsupported = TICKET_KIND.to_string();
+        BASE32_NOPAD.encode_append(&bytes, &mut unsupported);
+        assert!(GroupTicket::deserialize(&unsupported.to_ascii_lowercase()).is_err());
+    }
+}
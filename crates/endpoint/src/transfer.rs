@@ -0,0 +1,247 @@
+use std::{path::PathBuf, pin::pin, sync::Arc, time::Duration};
+
+use eyre::{Result, bail};
+use futures::StreamExt;
+use iroh::EndpointId;
+use iroh_blobs::{
+    Hash,
+    api::{Store, proto::BlobStatus, remote::GetProgressItem},
+    protocol::GetRequest,
+};
+use n0_future::task::AbortOnDropHandle;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{BlobOwner, Storage};
+
+const MAX_RETRIES: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SharedBlob {
+    pub hash: String,
+    pub size: u64,
+}
+impl SharedBlob {
+    pub(crate) async fn new(store: &Store, hash: Hash) -> Result<Self> {
+        store.tags().set(BlobOwner::Share.tag(hash), hash).await?;
+        let BlobStatus::Complete { size } = store.status(hash).await? else {
+            bail!("文件导入不完整");
+        };
+        Ok(Self {
+            hash: hash.to_string(),
+            size,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum TransferEvent {
+    Progress { downloaded: u64 },
+    Retrying { attempt: u32, error: String },
+    Done { size: u64 },
+    Failed { error: String },
+}
+
+#[derive(Clone)]
+pub struct Transfer {
+    receiver: async_channel::Receiver<TransferEvent>,
+    task: Arc<AbortOnDropHandle<()>>,
+}
+impl Transfer {
+    pub fn spawn(
+        endpoint: iroh::Endpoint,
+        storage: Storage,
+        remote_id: EndpointId,
+        request: GetRequest,
+        exports: Vec<(Hash, PathBuf)>,
+    ) -> Self {
+        let (sender, receiver) = async_channel::bounded(32);
+        let task = n0_future::task::spawn(async move {
+            let event =
+                match download(&endpoint, &storage, remote_id, request, exports, &sender).await {
+                    Ok(size) => TransferEvent::Done { size },
+                    Err(err) => TransferEvent::Failed {
+                        error: err.to_string(),
+                    },
+                };
+            sender.send(event).await.ok();
+        });
+        Self {
+            receiver,
+            task: Arc::new(AbortOnDropHandle::new(task)),
+        }
+    }
+    pub async fn next_event(&self) -> Option<TransferEvent> {
+        self.receiver.recv().await.ok()
+    }
+    pub fn cancel(&self) {
+        self.task.abort();
+        self.receiver.close();
+    }
+}
+
+async fn download(
+    endpoint: &iroh::Endpoint,
+    storage: &Storage,
+    remote_id: EndpointId,
+    request: GetRequest,
+    exports: Vec<(Hash, PathBuf)>,
+    sender: &async_channel::Sender<TransferEvent>,
+) -> Result<u64> {
+    let store = storage.store();
+    storage.tag(request.content(), BlobOwner::Download).await?;
+    let mut attempt = 0;
+    while let Err(err) = fetch(endpoint, store, remote_id, &request, sender).await {
+        if attempt >= MAX_RETRIES {
+            return Err(err);
+        }
+        attempt += 1;
+        sender
+            .send(TransferEvent::Retrying {
+                attempt,
+                error: err.to_string(),
+            })
+            .await
+            .ok();
+        n0_future::time::sleep(RETRY_DELAY * attempt).await;
+    }
+    let local = store.remote().local_for_request(request.clone()).await?;
+    if !local.is_complete() {
+        bail!("文件下载不完整");
+    }
+    for (hash, dest) in exports {
+        #[cfg(not(target_family = "wasm"))]
+        {
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            store.export(hash, dest).await?;
+        }
+        #[cfg(target_family = "wasm")]
+        {
+            let _ = (hash, dest);
+            bail!("当前平台不支持文件存储");
+        }
+    }
+    storage.cache(request.content()).await?;
+    storage.untag(request.hash, BlobOwner::Download).await?;
+    Ok(local.local_bytes())
+}
+
+async fn fetch(
+    endpoint: &iroh::Endpoint,
+    store: &Store,
+    remote_id: EndpointId,
+    request: &GetRequest,
+    sender: &async_channel::Sender<TransferEvent>,
+) -> Result<()> {
+    let local = store.remote().local_for_request(request.clone()).await?;
+    if local.is_complete() {
+        return Ok(());
+    }
+    let local_bytes = local.local_bytes();
+    let connection = endpoint.connect(remote_id, iroh_blobs::ALPN).await?;
+    let mut progress = pin!(
+        store
+            .remote()
+            .execute_get(connection, local.missing())
+            .stream()
+    );
+    while let Some(item) = progress.next().await {
+        match item {
+            GetProgressItem::Progress(bytes) => {
+                sender
+                    .try_send(TransferEvent::Progress {
+                        downloaded: local_bytes + bytes,
+                    })
+                    .ok();
+            }
+            GetProgressItem::Done(_) => return Ok(()),
+            GetProgressItem::Error(err) => return Err(err.into()),
+        }
+    }
+    bail!("下载意外中断")
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+    use crate::{
+        AddressLookupOption, Endpoint, LocalPerson, RelayOption, StoreOption, policy::PolicyOption,
+    };
+
+    async fn endpoint() -> Endpoint {
+        Endpoint::builder(
+            SecretKey::generate(&mut rand::rng()).to_bytes().to_vec(),
+            LocalPerson {
+                name: "测试".to_string(),
+                avatar: None,
+                bio: String::new(),
+            },
+        )
+        .relay(RelayOption::Disabled)
+        .address_lookup(AddressLookupOption::disabled())
+        .store(StoreOption::Memory)
+        .policy(PolicyOption::Memory)
+        .bind()
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn resumes_interrupted_download() {
+        let alice = endpoint().await;
+        let bob = endpoint().await;
+        alice.add_friend(bob.id()).await.unwrap();
+        bob.memory_lookup
+            .add_endpoint_info(alice.router.endpoint().addr());
+        let shared = alice.share_bytes(vec![7; 32 << 20]).await.unwrap();
+        let hash: Hash = shared.hash.parse().unwrap();
+
+        let handle = bob.download(alice.id(), shared.hash.clone(), None).unwrap();
+        loop {
+            match bob.transfer_next_event(handle).await.unwrap().unwrap() {
+                TransferEvent::Progress { downloaded } if downloaded >= 1 << 20 => break,
+                TransferEvent::Progress { .. } | TransferEvent::Retrying { .. } => {}
+                _ => panic!("下载过早结束"),
+            }
+        }
+        bob.cancel_transfer(handle);
+        let partial = bob
+            .storage
+            .store()
+            .remote()
+            .local(hash)
+            .await
+            .unwrap()
+            .local_bytes();
+        assert!(partial > 0 && partial < shared.size);
+        assert_eq!(bob.storage_usage().await.unwrap().download, partial);
+
+        let handle = bob.download(alice.id(), shared.hash.clone(), None).unwrap();
+        let mut resumed_from = None;
+        loop {
+            match bob.transfer_next_event(handle).await.unwrap().unwrap() {
+                TransferEvent::Progress { downloaded } => {
+                    resumed_from.get_or_insert(downloaded);
+                }
+                TransferEvent::Done { size } => {
+                    assert_eq!(size, shared.size);
+                    break;
+                }
+                TransferEvent::Retrying { .. } => {}
+                TransferEvent::Failed { error } => panic!("{}", error),
+            }
+        }
+        assert!(resumed_from.is_none_or(|v| v >= partial));
+        let usage = bob.storage_usage().await.unwrap();
+        assert_eq!(usage.download, 0);
+        assert_eq!(usage.cache, shared.size);
+
+        alice.close().await.unwrap();
+        bob.close().await.unwrap();
+    }
+}
@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use person_protocol::{
+    FriendIntro, GroupInvitation, Profile, Request, Response, frame, hello::Hello, v1,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = frame::decode::<Hello>(data);
+    let _ = frame::decode::<Request>(data);
+    let _ = frame::decode::<Response>(data);
+    let _ = frame::decode::<FriendIntro>(data);
+    let _ = frame::decode::<Profile>(data);
+    let _ = frame::decode::<GroupInvitation>(data);
+    let _ = frame::decode::<v1::Request>(data);
+    let _ = frame::decode::<v1::Response>(data);
+});
@@ -0,0 +1,265 @@
+use std::time::Duration;
+
+use iroh::endpoint::{ReadExactError, RecvStream, SendStream, WriteError};
+use n0_error::{e, stack_error};
+use rkyv::{
+    Archive, Deserialize, Serialize,
+    api::high::{HighSerializer, HighValidator},
+    bytecheck::CheckBytes,
+    de::Pool,
+    rancor::{self, Strategy},
+    ser::allocator::ArenaHandle,
+    util::AlignedVec,
+};
+
+pub const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[stack_error(derive, add_meta, std_sources)]
+pub enum ProtocolError {
+    #[error("帧长度{length}超出上限{max_size}")]
+    TooLarge { length: usize, max_size: usize },
+    #[error("读取帧超时")]
+    Timeout {},
+    #[error("读取帧失败")]
+    Read { source: ReadExactError },
+    #[error("写入帧失败")]
+    Write { source: WriteError },
+    #[error("帧数据校验失败")]
+    Invalid { source: rancor::Error },
+}
+
+pub fn encode<T>(value: &T) -> Result<AlignedVec, ProtocolError>
+where
+    T: for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+{
+    rkyv::to_bytes::<rancor::Error>(value).map_err(|source| e!(ProtocolError::Invalid, source))
+}
+
+pub fn decode<T>(bytes: &[u8]) -> Result<T, ProtocolError>
+where
+    T: Archive,
+    T::Archived: for<'a> CheckBytes<HighValidator<'a, rancor::Error>>
+        + Deserialize<T, Strategy<Pool, rancor::Error>>,
+{
+    let mut aligned = AlignedVec::<16>::with_capacity(bytes.len());
+    aligned.extend_from_slice(bytes);
+    rkyv::from_bytes::<T, rancor::Error>(&aligned)
+        .map_err(|source| e!(ProtocolError::Invalid, source))
+}
+
+pub async fn write<T>(send: &mut SendStream, value: &T) -> Result<(), ProtocolError>
+where
+    T: for<'a> Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+{
+    write_bytes(send, &encode(value)?).await
+}
+
+pub async fn write_bytes(send: &mut SendStream, bytes: &[u8]) -> Result<(), ProtocolError> {
+    send.write_all(&(bytes.len() as u32).to_be_bytes())
+        .await
+        .map_err(|source| e!(ProtocolError::Write, source))?;
+    send.write_all(bytes)
+        .await
+        .map_err(|source| e!(ProtocolError::Write, source))?;
+    Ok(())
+}
+
+pub async fn read<T>(
+    recv: &mut RecvStream,
+    max_size: usize,
+    timeout: Option<Duration>,
+) -> Result<T, ProtocolError>
+where
+    T: Archive,
+    T::Archived: for<'a> CheckBytes<HighValidator<'a, rancor::Error>>
+        + Deserialize<T, Strategy<Pool, rancor::Error>>,
+{
+    let read = async {
+        let mut length = [0; 4];
+        recv.read_exact(&mut length)
+            .await
+            .map_err(|source| e!(ProtocolError::Read, source))?;
+        let length = u32::from_be_bytes(length) as usize;
+        if length > max_size {
+            return Err(e!(ProtocolError::TooLarge { length, max_size }));
+        }
+        let mut bytes = AlignedVec::<16>::with_capacity(length);
+        bytes.resize(length, 0);
+        recv.read_exact(&mut bytes)
+            .await
+            .map_err(|source| e!(ProtocolError::Read, source))?;
+        rkyv::from_bytes::<T, rancor::Error>(&bytes)
+            .map_err(|source| e!(ProtocolError::Invalid, source))
+    };
+    match timeout {
+        Some(timeout) => n0_future::time::timeout(timeout, read)
+            .await
+            .map_err(|_| e!(ProtocolError::Timeout))?,
+        None => read.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::{Endpoint, RelayMode, SecretKey, endpoint::Connection};
+
+    use super::*;
+    use crate::{
+        Avatar, PROFILE_MAX_SIZE, Person, Profile, REQUEST_MAX_SIZE, Request, Response,
+        privacy::Audience,
+    };
+
+    const ALPN: &[u8] = b"frame/test";
+
+    async fn connect() -> (Endpoint, Endpoint, Connection, Connection) {
+        let server = Endpoint::empty_builder(RelayMode::Disabled)
+            .alpns(vec![ALPN.to_vec()])
+            .bind()
+            .await
+            .unwrap();
+        let client = Endpoint::empty_builder(RelayMode::Disabled)
+            .bind()
+            .await
+            .unwrap();
+        let (client_connection, server_connection) = tokio::join!(
+            async { client.connect(server.addr(), ALPN).await.unwrap() },
+            async { server.accept().await.unwrap().await.unwrap() },
+        );
+        (server, client, client_connection, server_connection)
+    }
+
+    fn person() -> Person {
+        Person {
+            name: "name".to_string(),
+            avatar: Some(Avatar {
+                hash: [1; 32],
+                size: 1024,
+            }),
+            bio: "bio".to_string(),
+        }
+    }
+
+    fn responses() -> Vec<Response> {
+        vec![
+            Response::Person(person()),
+            Response::Friend(true),
+            Response::Chat(false),
+            Response::Expired,
+            Response::Ack,
+            Response::FriendStatus(true),
+            Response::Unchanged,
+            Response::Profile(Box::new(Profile::new(
+                &SecretKey::from_bytes(&[7; 32]),
+                person(),
+                7,
+                Audience::Friends,
+            ))),
+            Response::GroupInvite(false),
+            Response::GroupKey(true),
+        ]
+    }
+
+    #[tokio::test]
+    async fn round_trips_every_request_and_response() {
+        let requests = [
+            Request::Person,
+            Request::Friend,
+            Request::Chat,
+            Request::Unfriend,
+            Request::FriendStatus,
+            Request::PersonIfNewer,
+            Request::PersonUpdate,
+            Request::GroupInvite,
+            Request::GroupKey,
+        ];
+        let (_server, _client, client, server) = connect().await;
+        let (mut send, _recv) = client.open_bi().await.unwrap();
+        for request in &requests {
+            write(&mut send, request).await.unwrap();
+        }
+        for response in &responses() {
+            write(&mut send, response).await.unwrap();
+        }
+        send.finish().unwrap();
+        let (_send, mut recv) = server.accept_bi().await.unwrap();
+        for request in requests {
+            let read = read::<Request>(&mut recv, REQUEST_MAX_SIZE, Some(READ_TIMEOUT))
+                .await
+                .unwrap();
+            assert_eq!(read, request);
+        }
+        for response in responses() {
+            let read = read::<Response>(&mut recv, PROFILE_MAX_SIZE, Some(READ_TIMEOUT))
+                .await
+                .unwrap();
+            assert_eq!(
+                encode(&read).unwrap().as_slice(),
+                encode(&response).unwrap().as_slice()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_oversize_frame() {
+        let (_server, _client, client, server) = connect().await;
+        let (mut send, _recv) = client.open_bi().await.unwrap();
+        write_bytes(&mut send, &[0; 64]).await.unwrap();
+        send.finish().unwrap();
+        let (_send, mut recv) = server.accept_bi().await.unwrap();
+        let err = read::<Request>(&mut recv, 16, Some(READ_TIMEOUT))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            ProtocolError::TooLarge {
+                length: 64,
+                max_size: 16,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_frame() {
+        let (_server, _client, client, server) = connect().await;
+        let (mut send, _recv) = client.open_bi().await.unwrap();
+        send.write_all(&100u32.to_be_bytes()).await.unwrap();
+        send.write_all(&[0; 10]).await.unwrap();
+        send.finish().unwrap();
+        let (_send, mut recv) = server.accept_bi().await.unwrap();
+        let err = read::<Request>(&mut recv, REQUEST_MAX_SIZE, Some(READ_TIMEOUT))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProtocolError::Read { .. }));
+    }
+
+    #[tokio::test]
+    async fn times_out_on_stalled_frame() {
+        let (_server, _client, client, server) = connect().await;
+        let (mut send, _recv) = client.open_bi().await.unwrap();
+        send.write_all(&100u32.to_be_bytes()).await.unwrap();
+        send.write_all(&[0; 10]).await.unwrap();
+        let (_send, mut recv) = server.accept_bi().await.unwrap();
+        let err = read::<Request>(
+            &mut recv,
+            REQUEST_MAX_SIZE,
+            Some(Duration::from_millis(200)),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err, ProtocolError::Timeout { .. }));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_frame() {
+        let (_server, _client, client, server) = connect().await;
+        let (mut send, _recv) = client.open_bi().await.unwrap();
+        write_bytes(&mut send, &[0xff; 8]).await.unwrap();
+        send.finish().unwrap();
+        let (_send, mut recv) = server.accept_bi().await.unwrap();
+        let err = read::<Request>(&mut recv, REQUEST_MAX_SIZE, Some(READ_TIMEOUT))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ProtocolError::Invalid { .. }));
+    }
+}
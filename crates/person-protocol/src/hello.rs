@@ -0,0 +1,83 @@
+use eyre::Result;
+use iroh::endpoint::Connection;
+use rkyv::Archive;
+
+use crate::frame;
+
+pub const VERSION: u32 = 2;
+pub const HELLO_MAX_SIZE: usize = 64;
+
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Features(u64);
+impl Features {
+    pub const SESSION: Self = Self(1 << 0);
+    pub const EXPIRY: Self = Self(1 << 1);
+    pub const FRIEND_INTRO: Self = Self(1 << 2);
+    pub const FRIENDSHIP: Self = Self(1 << 3);
+    pub const PROFILE: Self = Self(1 << 4);
+    pub const GROUP_INVITE: Self = Self(1 << 5);
+    pub const GROUP_KEY: Self = Self(1 << 6);
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+    pub const fn all() -> Self {
+        Self(
+            Self::SESSION.0
+                | Self::EXPIRY.0
+                | Self::FRIEND_INTRO.0
+                | Self::FRIENDSHIP.0
+                | Self::PROFILE.0
+                | Self::GROUP_INVITE.0
+                | Self::GROUP_KEY.0,
+        )
+    }
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+    pub fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy)]
+pub struct Hello {
+    pub version: u32,
+    pub features: Features,
+}
+impl Hello {
+    pub fn local() -> Self {
+        Self {
+            version: VERSION,
+            features: Features::all(),
+        }
+    }
+    pub fn legacy() -> Self {
+        Self {
+            version: 1,
+            features: Features::empty(),
+        }
+    }
+    fn negotiate(self, remote: Hello) -> Self {
+        Self {
+            version: self.version.min(remote.version),
+            features: self.features.intersection(remote.features),
+        }
+    }
+}
+
+pub(crate) async fn connect(connection: &Connection) -> Result<Hello> {
+    let (mut send, mut recv) = connection.open_bi().await?;
+    frame::write(&mut send, &Hello::local()).await?;
+    send.finish()?;
+    let remote = frame::read::<Hello>(&mut recv, HELLO_MAX_SIZE, Some(frame::READ_TIMEOUT)).await?;
+    Ok(Hello::local().negotiate(remote))
+}
+
+pub(crate) async fn accept(connection: &Connection) -> Result<Hello> {
+    let (mut send, mut recv) = connection.accept_bi().await?;
+    let remote = frame::read::<Hello>(&mut recv, HELLO_MAX_SIZE, Some(frame::READ_TIMEOUT)).await?;
+    frame::write(&mut send, &Hello::local()).await?;
+    send.finish()?;
+    Ok(Hello::local().negotiate(remote))
+}
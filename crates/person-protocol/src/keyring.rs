@@ -0,0 +1,180 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use iroh::EndpointId;
+use parking_lot::Mutex;
+
+struct Entry {
+    hash: [u8; 32],
+    admins: BTreeSet<EndpointId>,
+    key: Option<[u8; 32]>,
+}
+
+#[derive(Default)]
+struct Keys {
+    entries: HashMap<([u8; 32], u64), Entry>,
+    listeners: Vec<async_channel::Sender<([u8; 32], u64)>>,
+}
+impl Keys {
+    fn prune(&mut self, group: [u8; 32], epoch: u64) {
+        self.entries
+            .retain(|(g, e), _| *g != group || e.saturating_add(1) >= epoch);
+    }
+}
+impl std::fmt::Debug for Keys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keys")
+            .field("keys", &self.entries.len())
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Keyring(Arc<Mutex<Keys>>);
+impl Keyring {
+    pub fn set(&self, group: [u8; 32], epoch: u64, key: [u8; 32]) {
+        let mut keys = self.0.lock();
+        keys.prune(group, epoch);
+        keys.entries.insert(
+            (group, epoch),
+            Entry {
+                hash: key_hash(&key),
+                admins: Default::default(),
+                key: Some(key),
+            },
+        );
+    }
+    pub fn want(
+        &self,
+        group: [u8; 32],
+        epoch: u64,
+        hash: [u8; 32],
+        admins: impl IntoIterator<Item = EndpointId>,
+    ) {
+        let mut keys = self.0.lock();
+        keys.prune(group, epoch);
+        let entry = keys.entries.entry((group, epoch)).or_insert_with(|| Entry {
+            hash,
+            admins: Default::default(),
+            key: None,
+        });
+        if entry.hash != hash {
+            entry.hash = hash;
+            entry.key = None;
+        }
+        entry.admins = admins.into_iter().collect();
+    }
+    pub fn offer(&self, remote_id: EndpointId, group: [u8; 32], epoch: u64, key: [u8; 32]) -> bool {
+        let mut keys = self.0.lock();
+        let Some(entry) = keys.entries.get_mut(&(group, epoch)) else {
+            return false;
+        };
+        if !entry.admins.contains(&remote_id) || key_hash(&key) != entry.hash {
+            return false;
+        }
+        entry.key = Some(key);
+        keys.listeners
+            .retain(|v| v.try_send((group, epoch)).is_ok());
+        true
+    }
+    pub fn get(&self, group: [u8; 32], epoch: u64) -> Option<[u8; 32]> {
+        self.0
+            .lock()
+            .entries
+            .get(&(group, epoch))
+            .and_then(|v| v.key)
+    }
+    pub fn remove(&self, group: [u8; 32]) {
+        self.0.lock().entries.retain(|(g, _), _| *g != group);
+    }
+    pub fn subscribe(&self) -> async_channel::Receiver<([u8; 32], u64)> {
+        let (sender, receiver) = async_channel::unbounded();
+        self.0.lock().listeners.push(sender);
+        receiver
+    }
+}
+
+pub fn key_hash(key: &[u8; 32]) -> [u8; 32] {
+    *blake3::hash(key).as_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::SecretKey;
+
+    use super::*;
+
+    fn id() -> EndpointId {
+        SecretKey::generate(&mut rand::rng()).public()
+    }
+
+    #[test]
+    fn offer_requires_admin_sender() {
+        let keyring = Keyring::default();
+        let (admin, member) = (id(), id());
+        let key = rand::random();
+        keyring.want([1; 32], 1, key_hash(&key), [admin]);
+        assert!(!keyring.offer(member, [1; 32], 1, key));
+        assert_eq!(keyring.get([1; 32], 1), None);
+        assert!(keyring.offer(admin, [1; 32], 1, key));
+        assert_eq!(keyring.get([1; 32], 1), Some(key));
+    }
+
+    #[test]
+    fn offer_notifies_subscribers() {
+        let keyring = Keyring::default();
+        let admin = id();
+        let key = rand::random();
+        let keys = keyring.subscribe();
+        keyring.want([1; 32], 1, key_hash(&key), [admin]);
+        assert!(!keyring.offer(admin, [1; 32], 1, rand::random()));
+        assert!(keys.try_recv().is_err());
+        assert!(keyring.offer(admin, [1; 32], 1, key));
+        assert_eq!(keys.try_recv().unwrap(), ([1; 32], 1));
+    }
+
+    #[test]
+    fn offer_requires_matching_hash() {
+        let keyring = Keyring::default();
+        let admin = id();
+        let key = rand::random();
+        keyring.want([1; 32], 1, key_hash(&key), [admin]);
+        assert!(!keyring.offer(admin, [1; 32], 1, rand::random()));
+        assert_eq!(keyring.get([1; 32], 1), None);
+    }
+
+    #[test]
+    fn offer_requires_wanted_key() {
+        let keyring = Keyring::default();
+        let admin = id();
+        let key = rand::random();
+        keyring.want([1; 32], 1, key_hash(&key), [admin]);
+        assert!(!keyring.offer(admin, [1; 32], 2, key));
+        assert!(!keyring.offer(admin, [2; 32], 1, key));
+        assert_eq!(keyring.get([1; 32], 2), None);
+        assert_eq!(keyring.get([2; 32], 1), None);
+    }
+
+    #[test]
+    fn prunes_old_epochs_and_removed_groups() {
+        let keyring = Keyring::default();
+        for epoch in 0..3 {
+            keyring.set([1; 32], epoch, [epoch as u8; 32]);
+        }
+        keyring.set([2; 32], 0, [0; 32]);
+        assert_eq!(keyring.get([1; 32], 0), None);
+        assert_eq!(keyring.get([1; 32], 1), Some([1; 32]));
+        assert_eq!(keyring.get([1; 32], 2), Some([2; 32]));
+
+        keyring.want([1; 32], 3, key_hash(&rand::random()), [id()]);
+        assert_eq!(keyring.get([1; 32], 1), None);
+        assert_eq!(keyring.get([1; 32], 2), Some([2; 32]));
+
+        keyring.remove([1; 32]);
+        assert_eq!(keyring.get([1; 32], 2), None);
+        assert_eq!(keyring.get([2; 32], 0), Some([0; 32]));
+    }
+}
@@ -1,29 +1,150 @@
-use std::sync::Arc;
+pub mod frame;
+pub mod hello;
+pub mod keyring;
+pub mod limit;
+pub mod policy;
+pub mod privacy;
+pub mod v1;
 
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use async_channel::TrySendError;
 use eyre::{Result, bail, eyre};
 use futures::channel::oneshot;
 use iroh::{
-    Endpoint, EndpointId,
-    endpoint::Connection,
+    Endpoint, EndpointId, SecretKey, Signature,
+    endpoint::{
+        ConnectError, ConnectingError, Connection, ConnectionError, RecvStream, SendStream,
+        TransportErrorCode, VarInt,
+    },
     protocol::{AcceptError, ProtocolHandler},
 };
-use rkyv::Archive;
+use n0_future::time::SystemTime;
+use parking_lot::Mutex;
+use rkyv::{Archive, util::AlignedVec};
 use strum::Display;
 
-pub const ALPN: &[u8] = b"person/v1";
+use crate::{
+    hello::{Features, Hello},
+    keyring::Keyring,
+    limit::{Limiter, Limits, Metrics, OverflowPolicy},
+    policy::Policy,
+    privacy::{Audience, Privacy},
+};
+
+pub const ALPN: &[u8] = b"person/v2";
+
+pub const REQUEST_MAX_SIZE: usize = 1024;
+pub const RESPONSE_MAX_SIZE: usize = 1024;
+pub const PERSON_RESPONSE_MAX_SIZE: usize = 64 * 1024;
+pub const FRIEND_INTRO_MAX_SIZE: usize = PERSON_RESPONSE_MAX_SIZE + 4 * 1024;
+pub const PROFILE_MAX_SIZE: usize = PERSON_RESPONSE_MAX_SIZE + 1024;
+pub const AVATAR_MAX_SIZE: u64 = 4 * 1024 * 1024;
+pub const GREETING_MAX_LEN: usize = 512;
+pub const GROUP_INVITE_MAX_SIZE: usize = 8 * 1024;
+pub const GROUP_NAME_MAX_LEN: usize = 256;
+pub const MAX_CLOCK_SKEW: Duration = Duration::from_secs(10 * 60);
 
-#[derive(Archive, rkyv::Serialize, rkyv::Deserialize)]
-enum Request {
+const NO_APPLICATION_PROTOCOL: u8 = 120;
+
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Request {
     Person,
     Friend,
     Chat,
+    Unfriend,
+    FriendStatus,
+    PersonIfNewer,
+    PersonUpdate,
+    GroupInvite,
+    GroupKey,
+}
+impl Request {
+    fn response_max_size(&self) -> usize {
+        match self {
+            Request::Person => PERSON_RESPONSE_MAX_SIZE,
+            Request::PersonIfNewer => PROFILE_MAX_SIZE,
+            Request::Friend
+            | Request::Chat
+            | Request::Unfriend
+            | Request::FriendStatus
+            | Request::PersonUpdate
+            | Request::GroupInvite
+            | Request::GroupKey => RESPONSE_MAX_SIZE,
+        }
+    }
+    fn response_timeout(&self, timeouts: &Timeouts) -> Duration {
+        match self {
+            Request::Person
+            | Request::Unfriend
+            | Request::FriendStatus
+            | Request::PersonIfNewer
+            | Request::PersonUpdate
+            | Request::GroupKey => frame::READ_TIMEOUT,
+            Request::Friend | Request::Chat | Request::GroupInvite => timeouts.wait(),
+        }
+    }
+    fn required_features(&self) -> Features {
+        match self {
+            Request::Person | Request::Friend | Request::Chat => Features::empty(),
+            Request::Unfriend | Request::FriendStatus => Features::FRIENDSHIP,
+            Request::PersonIfNewer | Request::PersonUpdate => Features::PROFILE,
+            Request::GroupInvite => Features::GROUP_INVITE,
+            Request::GroupKey => Features::GROUP_KEY,
+        }
+    }
 }
 
-#[derive(Archive, rkyv::Serialize, rkyv::Deserialize)]
-enum Response {
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Debug)]
+pub enum Response {
     Person(Person),
     Friend(bool),
     Chat(bool),
+    Expired,
+    Ack,
+    FriendStatus(bool),
+    Unchanged,
+    Profile(Box<Profile>),
+    GroupInvite(bool),
+    GroupKey(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", content = "value")]
+pub enum Outcome<T = ()> {
+    Accepted(T),
+    Rejected,
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Timeouts {
+    pub respond_ms: u64,
+    pub wait_ms: u64,
+}
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            respond_ms: 60 * 1000,
+            wait_ms: 90 * 1000,
+        }
+    }
+}
+impl Timeouts {
+    fn respond(&self) -> Duration {
+        Duration::from_millis(self.respond_ms)
+    }
+    fn wait(&self) -> Duration {
+        Duration::from_millis(self.wait_ms)
+    }
 }
 
 #[derive(
@@ -31,24 +152,222 @@ enum Response {
 )]
 pub struct Person {
     pub name: String,
-    pub avatar: Option<Vec<u8>>,
+    pub avatar: Option<Avatar>,
     pub bio: String,
 }
 
+#[derive(
+    Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+pub struct Avatar {
+    #[serde(with = "hex")]
+    pub hash: [u8; 32],
+    pub size: u64,
+}
+
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone)]
+pub struct Profile {
+    pub person: Person,
+    pub version: u64,
+    pub audience: Audience,
+    pub signature: [u8; Signature::LENGTH],
+}
+impl Profile {
+    pub fn new(secret_key: &SecretKey, person: Person, version: u64, audience: Audience) -> Self {
+        let signature = secret_key
+            .sign(&Self::message(&person, version, audience))
+            .to_bytes();
+        Self {
+            person,
+            version,
+            audience,
+            signature,
+        }
+    }
+    pub fn verify(&self, author: EndpointId) -> Result<()> {
+        author
+            .verify(
+                &Self::message(&self.person, self.version, self.audience),
+                &Signature::from_bytes(&self.signature),
+            )
+            .map_err(|_| eyre!("个人信息签名无效"))
+    }
+    fn message(person: &Person, version: u64, audience: Audience) -> Vec<u8> {
+        let mut message = b"person/profile".to_vec();
+        message.extend_from_slice(&version.to_be_bytes());
+        message.push(audience as u8);
+        encode_person(&mut message, person);
+        message
+    }
+}
+
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone)]
+pub struct FriendIntro {
+    pub greeting: Option<String>,
+    pub person: Person,
+    pub timestamp: u64,
+    pub signature: [u8; Signature::LENGTH],
+}
+impl FriendIntro {
+    pub fn new(
+        secret_key: &SecretKey,
+        remote_id: EndpointId,
+        person: Person,
+        greeting: Option<String>,
+    ) -> Result<Self> {
+        check_greeting(greeting.as_deref())?;
+        let timestamp = now()?;
+        let signature = secret_key
+            .sign(&Self::message(
+                remote_id,
+                timestamp,
+                greeting.as_deref(),
+                &person,
+            ))
+            .to_bytes();
+        Ok(Self {
+            greeting,
+            person,
+            timestamp,
+            signature,
+        })
+    }
+    pub fn verify(&self, author: EndpointId, remote_id: EndpointId) -> Result<()> {
+        check_greeting(self.greeting.as_deref())?;
+        if now()?.abs_diff(self.timestamp) > MAX_CLOCK_SKEW.as_millis() as u64 {
+            bail!("好友请求时间戳无效");
+        }
+        author
+            .verify(
+                &Self::message(
+                    remote_id,
+                    self.timestamp,
+                    self.greeting.as_deref(),
+                    &self.person,
+                ),
+                &Signature::from_bytes(&self.signature),
+            )
+            .map_err(|_| eyre!("好友请求签名无效"))
+    }
+    fn message(
+        remote_id: EndpointId,
+        timestamp: u64,
+        greeting: Option<&str>,
+        person: &Person,
+    ) -> Vec<u8> {
+        let mut message = b"person/friend-intro".to_vec();
+        message.extend_from_slice(remote_id.as_bytes());
+        message.extend_from_slice(&timestamp.to_be_bytes());
+        match greeting {
+            Some(greeting) => {
+                message.push(1);
+                encode_field(&mut message, greeting.as_bytes());
+            }
+            None => message.push(0),
+        }
+        encode_person(&mut message, person);
+        message
+    }
+}
+
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone)]
+pub struct GroupInvitation {
+    pub ticket: String,
+    pub group_name: String,
+}
+impl GroupInvitation {
+    pub fn check(&self) -> Result<()> {
+        if self.group_name.len() > GROUP_NAME_MAX_LEN {
+            bail!("群组名称过长");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Clone, Copy)]
+pub struct GroupKey {
+    pub group: [u8; 32],
+    pub epoch: u64,
+    pub key: [u8; 32],
+}
+
 #[derive(Display)]
 pub enum Event {
     FriendRequest(FriendRequest),
     ChatRequest(ChatRequest),
+    RequestExpired(RequestExpired),
+    Unfriended(Unfriended),
+    PersonUpdated(PersonUpdated),
+    GroupInvite(GroupInvite),
+}
+impl Event {
+    pub fn request_id(&self) -> u64 {
+        match self {
+            Event::FriendRequest(friend_request) => friend_request.request_id(),
+            Event::ChatRequest(chat_request) => chat_request.request_id(),
+            Event::RequestExpired(request_expired) => request_expired.request_id(),
+            Event::Unfriended(unfriended) => unfriended.request_id(),
+            Event::PersonUpdated(person_updated) => person_updated.request_id(),
+            Event::GroupInvite(group_invite) => group_invite.request_id(),
+        }
+    }
+    pub fn remote_id(&self) -> EndpointId {
+        match self {
+            Event::FriendRequest(friend_request) => friend_request.remote_id(),
+            Event::ChatRequest(chat_request) => chat_request.remote_id(),
+            Event::RequestExpired(request_expired) => request_expired.remote_id(),
+            Event::Unfriended(unfriended) => unfriended.remote_id(),
+            Event::PersonUpdated(person_updated) => person_updated.remote_id(),
+            Event::GroupInvite(group_invite) => group_invite.remote_id(),
+        }
+    }
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            Event::FriendRequest(friend_request) => friend_request.timestamp(),
+            Event::ChatRequest(chat_request) => chat_request.timestamp(),
+            Event::RequestExpired(request_expired) => request_expired.timestamp(),
+            Event::Unfriended(unfriended) => unfriended.timestamp(),
+            Event::PersonUpdated(person_updated) => person_updated.timestamp(),
+            Event::GroupInvite(group_invite) => group_invite.timestamp(),
+        }
+    }
 }
 
 pub struct FriendRequest {
     response_sender: oneshot::Sender<bool>,
+    request_id: u64,
     remote_id: EndpointId,
+    intro: Option<FriendIntro>,
+    timestamp: u64,
 }
 impl FriendRequest {
+    pub fn greeting(&self) -> Option<&str> {
+        self.intro.as_ref().and_then(|v| v.greeting.as_deref())
+    }
+    pub fn person(&self) -> Option<&Person> {
+        self.intro.as_ref().map(|v| &v.person)
+    }
+    pub fn sent_at(&self) -> Option<u64> {
+        self.intro.as_ref().map(|v| v.timestamp)
+    }
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
     pub fn remote_id(&self) -> EndpointId {
         self.remote_id
     }
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
     pub fn accept(self) -> Result<()> {
         self.response_sender
             .send(true)
@@ -65,12 +384,20 @@ impl FriendRequest {
 
 pub struct ChatRequest {
     response_sender: oneshot::Sender<bool>,
+    request_id: u64,
     connection: Connection,
+    timestamp: u64,
 }
 impl ChatRequest {
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
     pub fn remote_id(&self) -> EndpointId {
         self.connection.remote_id()
     }
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
     pub fn accept(self) -> Result<Connection> {
         self.response_sender
             .send(true)
@@ -85,127 +412,975 @@ impl ChatRequest {
     }
 }
 
+pub struct RequestExpired {
+    request_id: u64,
+    remote_id: EndpointId,
+    timestamp: u64,
+}
+impl RequestExpired {
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+    pub fn remote_id(&self) -> EndpointId {
+        self.remote_id
+    }
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+pub struct Unfriended {
+    request_id: u64,
+    remote_id: EndpointId,
+    timestamp: u64,
+}
+impl Unfriended {
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+    pub fn remote_id(&self) -> EndpointId {
+        self.remote_id
+    }
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+pub struct PersonUpdated {
+    request_id: u64,
+    remote_id: EndpointId,
+    profile: Profile,
+    timestamp: u64,
+}
+impl PersonUpdated {
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+    pub fn remote_id(&self) -> EndpointId {
+        self.remote_id
+    }
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+pub struct GroupInvite {
+    response_sender: oneshot::Sender<bool>,
+    request_id: u64,
+    remote_id: EndpointId,
+    invitation: GroupInvitation,
+    timestamp: u64,
+}
+impl GroupInvite {
+    pub fn ticket(&self) -> &str {
+        &self.invitation.ticket
+    }
+    pub fn group_name(&self) -> &str {
+        &self.invitation.group_name
+    }
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+    pub fn remote_id(&self) -> EndpointId {
+        self.remote_id
+    }
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+    pub fn accept(self) -> Result<()> {
+        self.response_sender
+            .send(true)
+            .map_err(|_| eyre!("发送接受群组邀请消息失败"))?;
+        Ok(())
+    }
+    pub fn reject(self) -> Result<()> {
+        self.response_sender
+            .send(false)
+            .map_err(|_| eyre!("发送拒绝群组邀请消息失败"))?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+enum Payload {
+    FriendIntro(FriendIntro),
+    Version(u64),
+    Profile(Profile),
+    GroupInvitation(GroupInvitation),
+    GroupKey(GroupKey),
+}
+impl Payload {
+    fn feature(&self) -> Features {
+        match self {
+            Payload::FriendIntro(_) => Features::FRIEND_INTRO,
+            Payload::Version(_) | Payload::Profile(_) => Features::PROFILE,
+            Payload::GroupInvitation(_) => Features::GROUP_INVITE,
+            Payload::GroupKey(_) => Features::GROUP_KEY,
+        }
+    }
+    fn encode(&self) -> Result<AlignedVec> {
+        Ok(match self {
+            Payload::FriendIntro(intro) => frame::encode(intro)?,
+            Payload::Version(version) => frame::encode(version)?,
+            Payload::Profile(profile) => frame::encode(profile)?,
+            Payload::GroupInvitation(invitation) => frame::encode(invitation)?,
+            Payload::GroupKey(key) => frame::encode(key)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    connection: Connection,
+    hello: Hello,
+}
+impl Session {
+    fn is_persistent(&self) -> bool {
+        self.hello.features.contains(Features::SESSION)
+    }
+    fn close(&self) {
+        self.connection.close(VarInt::from_u32(0), b"done");
+    }
+    async fn exchange(
+        &self,
+        (mut send, mut recv): (SendStream, RecvStream),
+        request: Request,
+        payload: Option<Payload>,
+        avatars: &v1::Avatars,
+        timeouts: &Timeouts,
+    ) -> Result<Option<Response>> {
+        if !self.hello.features.contains(request.required_features()) {
+            bail!("对方版本过旧，不支持该请求");
+        }
+        let timeout = request.response_timeout(timeouts);
+        let exchange = async {
+            if self.connection.alpn() == v1::ALPN {
+                return v1::exchange((send, recv), request, avatars).await;
+            }
+            frame::write(&mut send, &request).await?;
+            if let Some(payload) = payload
+                && self.hello.features.contains(payload.feature())
+            {
+                frame::write_bytes(&mut send, &payload.encode()?).await?;
+            }
+            send.finish()?;
+            let response =
+                frame::read::<Response>(&mut recv, request.response_max_size(), None).await?;
+            if let Response::Profile(profile) = &response {
+                profile.verify(self.connection.remote_id())?;
+            }
+            Ok(response)
+        };
+        n0_future::time::timeout(timeout, exchange)
+            .await
+            .ok()
+            .transpose()
+    }
+}
+
+#[derive(Debug)]
+struct Profiles {
+    person: Person,
+    version: u64,
+    public: Arc<Profile>,
+    friends: Arc<Profile>,
+}
+impl Profiles {
+    fn new(secret_key: &SecretKey, person: Person, version: u64, privacy: &Privacy) -> Self {
+        let redact = |audience| {
+            Arc::new(Profile::new(
+                secret_key,
+                privacy.redact(&person, audience),
+                version,
+                audience,
+            ))
+        };
+        Self {
+            public: redact(Audience::Public),
+            friends: redact(Audience::Friends),
+            person,
+            version,
+        }
+    }
+    fn get(&self, audience: Audience) -> Arc<Profile> {
+        match audience {
+            Audience::Public => self.public.clone(),
+            Audience::Friends => self.friends.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PersonProtocol {
     endpoint: Endpoint,
-    person: Arc<Person>,
+    profiles: Arc<Mutex<Profiles>>,
     event_sender: async_channel::Sender<Event>,
     event_receiver: async_channel::Receiver<Event>,
+    timeouts: Timeouts,
+    limiter: Limiter,
+    policy: Policy,
+    keyring: Keyring,
+    avatars: v1::Avatars,
+    next_request_id: Arc<AtomicU64>,
+    session_cache: Arc<Mutex<HashMap<EndpointId, Session>>>,
+    legacy_peers: Arc<Mutex<HashSet<EndpointId>>>,
+    connections: Arc<Mutex<HashMap<EndpointId, Vec<Connection>>>>,
 }
 impl PersonProtocol {
-    pub fn new(endpoint: Endpoint, person: Person) -> Self {
-        let (event_sender, event_receiver) = async_channel::bounded(10);
-        Self {
+    pub fn new(
+        endpoint: Endpoint,
+        person: Person,
+        timeouts: Timeouts,
+        limits: Limits,
+        policy: Policy,
+    ) -> Result<Self> {
+        let (event_sender, event_receiver) = async_channel::bounded(limits.event_capacity.max(1));
+        let profiles = Profiles::new(endpoint.secret_key(), person, now()?, &policy.privacy());
+        Ok(Self {
             endpoint,
-            person: Arc::new(person),
+            profiles: Arc::new(Mutex::new(profiles)),
             event_sender,
             event_receiver,
+            timeouts,
+            limiter: Limiter::new(limits),
+            policy,
+            keyring: Default::default(),
+            avatars: Default::default(),
+            next_request_id: Default::default(),
+            session_cache: Default::default(),
+            legacy_peers: Default::default(),
+            connections: Default::default(),
+        })
+    }
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+    pub fn keyring(&self) -> &Keyring {
+        &self.keyring
+    }
+    pub fn avatars(&self) -> &v1::Avatars {
+        &self.avatars
+    }
+    pub fn profile(&self) -> (Person, u64) {
+        let profiles = self.profiles.lock();
+        (profiles.person.clone(), profiles.version)
+    }
+    pub fn set_person(&self, person: Person) -> Result<()> {
+        {
+            let mut profiles = self.profiles.lock();
+            let version = now()?.max(profiles.version + 1);
+            *profiles = Profiles::new(
+                self.endpoint.secret_key(),
+                person,
+                version,
+                &self.policy.privacy(),
+            );
         }
+        for id in self.policy.friends() {
+            self.push_profile(id);
+        }
+        Ok(())
+    }
+    fn push_profile(&self, id: EndpointId) {
+        let person_protocol = self.clone();
+        let payload = Payload::Profile((*self.profiles.lock().friends).clone());
+        n0_future::task::spawn(async move {
+            if let Err(err) = person_protocol
+                .request(id, Request::PersonUpdate, Some(payload))
+                .await
+            {
+                log::warn!("向{}推送个人信息失败：{}", id, err);
+            }
+        });
+    }
+    pub async fn block(&self, id: EndpointId) -> Result<()> {
+        self.policy.block(id).await?;
+        self.session_cache.lock().remove(&id);
+        for connection in self.connections.lock().remove(&id).unwrap_or_default() {
+            connection.close(VarInt::from_u32(0), b"blocked");
+        }
+        Ok(())
+    }
+    fn track(&self, connection: &Connection) {
+        let mut connections = self.connections.lock();
+        connections.retain(|_, v| {
+            v.retain(|v| v.close_reason().is_none());
+            !v.is_empty()
+        });
+        connections
+            .entry(connection.remote_id())
+            .or_default()
+            .push(connection.clone());
+    }
+    pub fn metrics(&self) -> Metrics {
+        self.limiter.metrics()
+    }
+    pub async fn set_privacy(&self, privacy: Privacy) -> Result<()> {
+        self.policy.set_privacy(privacy).await?;
+        self.set_person(self.profile().0)
+    }
+    fn visible_profile(&self, id: &EndpointId) -> Arc<Profile> {
+        self.profiles
+            .lock()
+            .get(Audience::of(self.policy.is_friend(id)))
+    }
+    fn is_dropped(&self, connection: &Connection, request: &Request) -> bool {
+        (matches!(request, Request::Friend | Request::Chat)
+            && !self.policy.is_allowed(&connection.remote_id()))
+            || (matches!(request, Request::GroupInvite | Request::Unfriend)
+                && !self.policy.is_friend(&connection.remote_id()))
+            || !self.limiter.check(connection.remote_id(), *request)
+    }
+    fn push_event(&self, event: Event) -> Result<()> {
+        let event = match self.event_sender.try_send(event) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Full(event)) => event,
+            Err(TrySendError::Closed(_)) => bail!("事件队列已关闭"),
+        };
+        if let OverflowPolicy::DropOldest = self.limiter.limits().overflow
+            && let Ok(oldest) = self.event_receiver.try_recv()
+        {
+            self.limiter.record_dropped();
+            log::warn!("事件队列已满，丢弃最早的{}事件", oldest);
+            if let Err(err) = self.event_sender.try_send(event) {
+                self.limiter.record_rejected();
+                log::warn!("事件队列已满，丢弃新的{}事件", err.into_inner());
+            }
+            return Ok(());
+        }
+        self.limiter.record_rejected();
+        log::warn!("事件队列已满，丢弃新的{}事件", event);
+        Ok(())
     }
     async fn handle_connection(&self, connection: Connection) -> Result<()> {
-        if let Ok((mut send, mut recv)) = connection.accept_bi().await {
-            if let Ok(data) = recv.read_to_end(usize::MAX).await {
-                match rkyv::from_bytes::<Request, rkyv::rancor::Error>(&data)? {
-                    Request::Person => {
-                        send.write_all(&rkyv::to_bytes::<rkyv::rancor::Error>(&Response::Person(
-                            (*self.person).clone(),
-                        ))?)
-                        .await?;
-                        send.finish()?;
-                        connection.closed().await;
-                    }
-                    Request::Friend => {
-                        let (sender, receiver) = oneshot::channel::<bool>();
-                        self.event_sender
-                            .send(Event::FriendRequest(FriendRequest {
-                                remote_id: connection.remote_id(),
-                                response_sender: sender,
-                            }))
-                            .await?;
-                        let result = receiver.await?;
-                        send.write_all(&rkyv::to_bytes::<rkyv::rancor::Error>(&Response::Friend(
-                            result,
-                        ))?)
-                        .await?;
-                        send.finish()?;
-                        connection.closed().await;
-                    }
-                    Request::Chat => {
-                        let (sender, receiver) = oneshot::channel::<bool>();
-                        self.event_sender
-                            .send(Event::ChatRequest(ChatRequest {
-                                response_sender: sender,
-                                connection,
-                            }))
-                            .await?;
-                        let result = receiver.await?;
-                        send.write_all(&rkyv::to_bytes::<rkyv::rancor::Error>(&Response::Chat(
-                            result,
-                        ))?)
+        let hello = hello::accept(&connection).await?;
+        let streams = Arc::new(AtomicUsize::new(0));
+        while let Ok((send, mut recv)) = connection.accept_bi().await {
+            let Some(permits) = self
+                .limiter
+                .acquire_stream(connection.remote_id(), &streams)
+                .zip(self.limiter.acquire_request())
+            else {
+                recv.stop(VarInt::from_u32(0)).ok();
+                continue;
+            };
+            let person_protocol = self.clone();
+            let connection = connection.clone();
+            n0_future::task::spawn(async move {
+                let _permits = permits;
+                if let Err(err) = person_protocol
+                    .handle_stream(connection, hello, send, recv)
+                    .await
+                {
+                    log::error!("{}", err);
+                }
+            });
+        }
+        Ok(())
+    }
+    async fn handle_legacy_connection(&self, connection: Connection) -> Result<()> {
+        let (mut send, mut recv) = connection.accept_bi().await?;
+        let Some(permit) = self.limiter.acquire_request() else {
+            connection.close(VarInt::from_u32(0), b"busy");
+            return Ok(());
+        };
+        let request = v1::read_request(&mut recv).await?;
+        if self.is_dropped(&connection, &request) {
+            return Ok(());
+        }
+        let linger = !matches!(request, Request::Chat);
+        let response = self
+            .response(connection.clone(), Hello::legacy(), request, None)
+            .await?;
+        v1::write_response(&mut send, response, &self.avatars).await?;
+        drop(permit);
+        if linger {
+            connection.closed().await;
+        }
+        Ok(())
+    }
+    async fn handle_stream(
+        &self,
+        connection: Connection,
+        hello: Hello,
+        mut send: SendStream,
+        mut recv: RecvStream,
+    ) -> Result<()> {
+        let request =
+            frame::read::<Request>(&mut recv, REQUEST_MAX_SIZE, Some(frame::READ_TIMEOUT)).await?;
+        if self.is_dropped(&connection, &request) {
+            return Ok(());
+        }
+        let payload = match request {
+            Request::Friend if hello.features.contains(Features::FRIEND_INTRO) => {
+                let intro = frame::read::<FriendIntro>(
+                    &mut recv,
+                    FRIEND_INTRO_MAX_SIZE,
+                    Some(frame::READ_TIMEOUT),
+                )
+                .await?;
+                intro.verify(connection.remote_id(), self.endpoint.id())?;
+                Some(Payload::FriendIntro(intro))
+            }
+            Request::PersonIfNewer => Some(Payload::Version(
+                frame::read::<u64>(&mut recv, REQUEST_MAX_SIZE, Some(frame::READ_TIMEOUT)).await?,
+            )),
+            Request::PersonUpdate => {
+                let profile =
+                    frame::read::<Profile>(&mut recv, PROFILE_MAX_SIZE, Some(frame::READ_TIMEOUT))
                         .await?;
-                        send.finish()?;
+                profile.verify(connection.remote_id())?;
+                Some(Payload::Profile(profile))
+            }
+            Request::GroupInvite => {
+                let invitation = frame::read::<GroupInvitation>(
+                    &mut recv,
+                    GROUP_INVITE_MAX_SIZE,
+                    Some(frame::READ_TIMEOUT),
+                )
+                .await?;
+                invitation.check()?;
+                Some(Payload::GroupInvitation(invitation))
+            }
+            Request::GroupKey => Some(Payload::GroupKey(
+                frame::read::<GroupKey>(&mut recv, REQUEST_MAX_SIZE, Some(frame::READ_TIMEOUT))
+                    .await?,
+            )),
+            _ => None,
+        };
+        let response = self.response(connection, hello, request, payload).await?;
+        frame::write(&mut send, &response).await?;
+        send.finish()?;
+        Ok(())
+    }
+    async fn response(
+        &self,
+        connection: Connection,
+        hello: Hello,
+        request: Request,
+        payload: Option<Payload>,
+    ) -> Result<Response> {
+        let request_id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let remote_id = connection.remote_id();
+        let expired = |response| {
+            if hello.features.contains(Features::EXPIRY) {
+                Response::Expired
+            } else {
+                response
+            }
+        };
+        Ok(match request {
+            Request::Person => Response::Person(self.visible_profile(&remote_id).person.clone()),
+            Request::Friend => {
+                let (sender, receiver) = oneshot::channel::<bool>();
+                self.push_event(Event::FriendRequest(FriendRequest {
+                    request_id,
+                    remote_id,
+                    intro: match payload {
+                        Some(Payload::FriendIntro(intro)) => Some(intro),
+                        _ => None,
+                    },
+                    response_sender: sender,
+                    timestamp: now()?,
+                }))?;
+                match self.answer(request_id, remote_id, receiver).await? {
+                    Some(true) => {
+                        self.policy.add_friend(remote_id).await?;
+                        Response::Friend(true)
                     }
+                    Some(false) => Response::Friend(false),
+                    None => expired(Response::Friend(false)),
                 }
             }
+            Request::Chat => {
+                let (sender, receiver) = oneshot::channel::<bool>();
+                self.push_event(Event::ChatRequest(ChatRequest {
+                    response_sender: sender,
+                    request_id,
+                    connection,
+                    timestamp: now()?,
+                }))?;
+                match self.answer(request_id, remote_id, receiver).await? {
+                    Some(result) => Response::Chat(result),
+                    None => expired(Response::Chat(false)),
+                }
+            }
+            Request::Unfriend => {
+                self.policy.remove_friend(remote_id).await?;
+                self.push_event(Event::Unfriended(Unfriended {
+                    request_id,
+                    remote_id,
+                    timestamp: now()?,
+                }))?;
+                Response::Ack
+            }
+            Request::FriendStatus => Response::FriendStatus(self.policy.is_friend(&remote_id)),
+            Request::PersonIfNewer => {
+                let Some(Payload::Version(version)) = payload else {
+                    bail!("请求数据缺失");
+                };
+                let profile = self.visible_profile(&remote_id);
+                if profile.version != version {
+                    Response::Profile(Box::new((*profile).clone()))
+                } else {
+                    Response::Unchanged
+                }
+            }
+            Request::PersonUpdate => {
+                let Some(Payload::Profile(profile)) = payload else {
+                    bail!("请求数据缺失");
+                };
+                if self.policy.is_friend(&remote_id) {
+                    self.push_event(Event::PersonUpdated(PersonUpdated {
+                        request_id,
+                        remote_id,
+                        profile,
+                        timestamp: now()?,
+                    }))?;
+                }
+                Response::Ack
+            }
+            Request::GroupInvite => {
+                let Some(Payload::GroupInvitation(invitation)) = payload else {
+                    bail!("请求数据缺失");
+                };
+                let (sender, receiver) = oneshot::channel::<bool>();
+                self.push_event(Event::GroupInvite(GroupInvite {
+                    response_sender: sender,
+                    request_id,
+                    remote_id,
+                    invitation,
+                    timestamp: now()?,
+                }))?;
+                match self.answer(request_id, remote_id, receiver).await? {
+                    Some(result) => Response::GroupInvite(result),
+                    None => expired(Response::GroupInvite(false)),
+                }
+            }
+            Request::GroupKey => {
+                let Some(Payload::GroupKey(key)) = payload else {
+                    bail!("请求数据缺失");
+                };
+                Response::GroupKey(self.keyring.offer(remote_id, key.group, key.epoch, key.key))
+            }
+        })
+    }
+    async fn answer(
+        &self,
+        request_id: u64,
+        remote_id: EndpointId,
+        receiver: oneshot::Receiver<bool>,
+    ) -> Result<Option<bool>> {
+        match n0_future::time::timeout(self.timeouts.respond(), receiver).await {
+            Ok(Ok(result)) => Ok(Some(result)),
+            Ok(Err(_)) => Ok(None),
+            Err(_) => {
+                self.push_event(Event::RequestExpired(RequestExpired {
+                    request_id,
+                    remote_id,
+                    timestamp: now()?,
+                }))?;
+                Ok(None)
+            }
         }
-        Ok(())
     }
     pub async fn next_event(&self) -> Result<Event> {
         Ok(self.event_receiver.recv().await?)
     }
+    async fn connect(&self, id: EndpointId) -> Result<Session> {
+        if !self.legacy_peers.lock().contains(&id) {
+            match self.endpoint.connect(id, ALPN).await {
+                Ok(connection) => {
+                    let hello = hello::connect(&connection).await?;
+                    return Ok(Session { connection, hello });
+                }
+                Err(err) if !is_alpn_mismatch(&err) => return Err(err.into()),
+                Err(_) => {
+                    log::warn!(
+                        "{}不支持{}协议，降级为旧版协议",
+                        id,
+                        String::from_utf8_lossy(ALPN)
+                    );
+                    self.legacy_peers.lock().insert(id);
+                }
+            }
+        }
+        let connection = self.endpoint.connect(id, v1::ALPN).await?;
+        Ok(Session {
+            connection,
+            hello: Hello::legacy(),
+        })
+    }
+    async fn session(&self, id: EndpointId) -> Result<Session> {
+        if let Some(session) = self
+            .session_cache
+            .lock()
+            .get(&id)
+            .filter(|v| v.connection.close_reason().is_none())
+        {
+            return Ok(session.clone());
+        }
+        let session = self.connect(id).await?;
+        if session.is_persistent() {
+            self.track(&session.connection);
+            self.session_cache.lock().insert(id, session.clone());
+        }
+        Ok(session)
+    }
+    async fn request(
+        &self,
+        id: EndpointId,
+        request: Request,
+        payload: Option<Payload>,
+    ) -> Result<Option<Response>> {
+        let mut session = self.session(id).await?;
+        let streams = match session.connection.open_bi().await {
+            Ok(streams) => Ok(streams),
+            Err(_) => {
+                self.session_cache.lock().remove(&id);
+                session = self.session(id).await?;
+                session.connection.open_bi().await
+            }
+        };
+        let response = match streams {
+            Ok(streams) => {
+                session
+                    .exchange(streams, request, payload, &self.avatars, &self.timeouts)
+                    .await
+            }
+            Err(err) => Err(err.into()),
+        };
+        if !session.is_persistent() {
+            session.close();
+        }
+        response
+    }
     pub async fn request_person(&self, id: EndpointId) -> Result<Person> {
-        let connection = self.endpoint.connect(id, ALPN).await?;
-        let (mut send, mut recv) = connection.open_bi().await?;
-        send.write_all(&rkyv::to_bytes::<rkyv::rancor::Error>(&Request::Person)?)
-            .await?;
-        send.finish()?;
-        let Response::Person(person) = rkyv::from_bytes::<Response, rkyv::rancor::Error>(
-            &recv.read_to_end(usize::MAX).await?,
-        )?
-        else {
-            bail!("响应数据非预期");
+        let Some(Response::Person(person)) = self.request(id, Request::Person, None).await? else {
+            bail!("请求个人信息失败");
         };
         Ok(person)
     }
-    pub async fn request_friend(&self, id: EndpointId) -> Result<bool> {
-        let connection = self.endpoint.connect(id, ALPN).await?;
-        let (mut send, mut recv) = connection.open_bi().await?;
-        send.write_all(&rkyv::to_bytes::<rkyv::rancor::Error>(&Request::Friend)?)
-            .await?;
-        send.finish()?;
-        let Response::Friend(result) = rkyv::from_bytes::<Response, rkyv::rancor::Error>(
-            &recv.read_to_end(usize::MAX).await?,
-        )?
+    pub async fn request_profile(&self, id: EndpointId, version: u64) -> Result<Option<Profile>> {
+        Ok(
+            match self
+                .request(id, Request::PersonIfNewer, Some(Payload::Version(version)))
+                .await?
+            {
+                Some(Response::Profile(profile)) => Some(*profile),
+                Some(Response::Unchanged) => None,
+                _ => bail!("请求个人信息失败"),
+            },
+        )
+    }
+    pub async fn request_friend(
+        &self,
+        id: EndpointId,
+        greeting: Option<String>,
+    ) -> Result<Outcome> {
+        let intro = FriendIntro::new(
+            self.endpoint.secret_key(),
+            id,
+            self.visible_profile(&id).person.clone(),
+            greeting,
+        )?;
+        Ok(
+            match self
+                .request(id, Request::Friend, Some(Payload::FriendIntro(intro)))
+                .await?
+            {
+                Some(Response::Friend(true)) => {
+                    self.policy.add_friend(id).await?;
+                    self.push_profile(id);
+                    Outcome::Accepted(())
+                }
+                Some(Response::Friend(false)) => Outcome::Rejected,
+                Some(Response::Expired) | None => Outcome::TimedOut,
+                Some(_) => bail!("响应数据非预期"),
+            },
+        )
+    }
+    pub async fn unfriend(&self, id: EndpointId) -> Result<()> {
+        self.policy.remove_friend(id).await?;
+        match self.request(id, Request::Unfriend, None).await {
+            Ok(Some(Response::Ack)) => {}
+            Ok(_) => log::warn!("通知{}解除好友关系失败：对方未确认", id),
+            Err(err) => log::warn!("通知{}解除好友关系失败：{}", id, err),
+        }
+        Ok(())
+    }
+    pub async fn request_friend_status(&self, id: EndpointId) -> Result<bool> {
+        let Some(Response::FriendStatus(result)) =
+            self.request(id, Request::FriendStatus, None).await?
         else {
-            bail!("响应数据非预期");
+            bail!("查询好友状态失败");
         };
         Ok(result)
     }
-    pub async fn request_chat(&self, id: EndpointId) -> Result<Option<Connection>> {
-        let connection = self.endpoint.connect(id, ALPN).await?;
-        let (mut send, mut recv) = connection.open_bi().await?;
-        send.write_all(&rkyv::to_bytes::<rkyv::rancor::Error>(&Request::Chat)?)
-            .await?;
-        send.finish()?;
-        let Response::Chat(result) = rkyv::from_bytes::<Response, rkyv::rancor::Error>(
-            &recv.read_to_end(usize::MAX).await?,
-        )?
+    pub async fn request_group_invite(
+        &self,
+        id: EndpointId,
+        invitation: GroupInvitation,
+    ) -> Result<Outcome> {
+        invitation.check()?;
+        Ok(
+            match self
+                .request(
+                    id,
+                    Request::GroupInvite,
+                    Some(Payload::GroupInvitation(invitation)),
+                )
+                .await?
+            {
+                Some(Response::GroupInvite(true)) => Outcome::Accepted(()),
+                Some(Response::GroupInvite(false)) => Outcome::Rejected,
+                Some(Response::Expired) | None => Outcome::TimedOut,
+                Some(_) => bail!("响应数据非预期"),
+            },
+        )
+    }
+    pub async fn send_group_key(&self, id: EndpointId, key: GroupKey) -> Result<bool> {
+        let Some(Response::GroupKey(accepted)) = self
+            .request(id, Request::GroupKey, Some(Payload::GroupKey(key)))
+            .await?
         else {
-            bail!("响应数据非预期");
+            bail!("发送群组密钥失败");
+        };
+        Ok(accepted)
+    }
+    pub async fn request_chat(&self, id: EndpointId) -> Result<Outcome<Connection>> {
+        let session = self.connect(id).await?;
+        let response = match session.connection.open_bi().await {
+            Ok(streams) => {
+                session
+                    .exchange(streams, Request::Chat, None, &self.avatars, &self.timeouts)
+                    .await
+            }
+            Err(err) => Err(err.into()),
+        };
+        let outcome = match response {
+            Ok(Some(Response::Chat(true))) => {
+                self.track(&session.connection);
+                return Ok(Outcome::Accepted(session.connection));
+            }
+            Ok(Some(Response::Chat(false))) => Ok(Outcome::Rejected),
+            Ok(Some(Response::Expired) | None) => Ok(Outcome::TimedOut),
+            Ok(Some(_)) => Err(eyre!("响应数据非预期")),
+            Err(err) => Err(err),
         };
-        if !result {
-            return Ok(None);
+        session.close();
+        outcome
+    }
+}
+fn is_alpn_mismatch(err: &ConnectError) -> bool {
+    let source = match err {
+        ConnectError::Connecting {
+            source: ConnectingError::ConnectionError { source, .. },
+            ..
+        } => source,
+        ConnectError::Connection { source, .. } => source,
+        _ => return false,
+    };
+    matches!(
+        source,
+        ConnectionError::ConnectionClosed(close)
+            if close.error_code == TransportErrorCode::crypto(NO_APPLICATION_PROTOCOL)
+    )
+}
+fn encode_person(message: &mut Vec<u8>, person: &Person) {
+    match &person.avatar {
+        Some(avatar) => {
+            message.push(1);
+            message.extend_from_slice(&avatar.hash);
+            message.extend_from_slice(&avatar.size.to_be_bytes());
         }
-        Ok(Some(connection))
+        None => message.push(0),
     }
+    encode_field(message, person.name.as_bytes());
+    encode_field(message, person.bio.as_bytes());
 }
+fn encode_field(message: &mut Vec<u8>, field: &[u8]) {
+    message.extend_from_slice(&(field.len() as u64).to_be_bytes());
+    message.extend_from_slice(field);
+}
+fn check_greeting(greeting: Option<&str>) -> Result<()> {
+    if greeting.is_some_and(|v| v.len() > GREETING_MAX_LEN) {
+        bail!("招呼语过长");
+    }
+    Ok(())
+}
+fn now() -> Result<u64> {
+    Ok(SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_millis() as u64)
+}
+
+mod hex {
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(
+            &bytes
+                .iter()
+                .map(|v| format!("{:02x}", v))
+                .collect::<String>(),
+        )
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        if hex.len() != 64 {
+            return Err(D::Error::custom("哈希长度无效"));
+        }
+        let mut bytes = [0; 32];
+        for (i, v) in bytes.iter_mut().enumerate() {
+            *v = hex
+                .get(i * 2..i * 2 + 2)
+                .and_then(|v| u8::from_str_radix(v, 16).ok())
+                .ok_or_else(|| D::Error::custom("哈希格式无效"))?;
+        }
+        Ok(bytes)
+    }
+}
+
 impl ProtocolHandler for PersonProtocol {
     async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
-        self.handle_connection(connection)
+        if !self.policy.is_allowed(&connection.remote_id()) {
+            connection.close(VarInt::from_u32(0), b"blocked");
+            return Ok(());
+        }
+        self.track(&connection);
+        match connection.alpn() {
+            v1::ALPN => self.handle_legacy_connection(connection).await,
+            _ => self.handle_connection(connection).await,
+        }
+        .map_err(|err| AcceptError::User {
+            source: n0_error::AnyError::from_std_box(err.into()),
+            meta: n0_error::meta(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iroh::{RelayMode, address_lookup::memory::MemoryLookup, protocol::Router};
+
+    use super::*;
+    use crate::privacy::Visibility;
+
+    async fn spawn(lookup: &MemoryLookup, person: Person) -> (Router, PersonProtocol) {
+        let endpoint = Endpoint::empty_builder(RelayMode::Disabled)
+            .address_lookup(lookup.clone())
+            .alpns(vec![ALPN.to_vec()])
+            .bind()
             .await
-            .map_err(|err| AcceptError::User {
-                source: n0_error::AnyError::from_std_box(err.into()),
-                meta: n0_error::meta(),
+            .unwrap();
+        lookup.add_endpoint_info(endpoint.addr());
+        let person_protocol = PersonProtocol::new(
+            endpoint.clone(),
+            person,
+            Default::default(),
+            Default::default(),
+            Policy::memory(),
+        )
+        .unwrap();
+        let router = Router::builder(endpoint)
+            .accept(ALPN, person_protocol.clone())
+            .spawn();
+        (router, person_protocol)
+    }
+
+    #[tokio::test]
+    async fn non_friends_get_friends_only_fields_stripped() {
+        let lookup = MemoryLookup::new();
+        let (alice_router, alice) = spawn(
+            &lookup,
+            Person {
+                name: "alice".into(),
+                avatar: Some(Avatar {
+                    hash: [1; 32],
+                    size: 1,
+                }),
+                bio: "secret".into(),
+            },
+        )
+        .await;
+        let (bob_router, bob) = spawn(
+            &lookup,
+            Person {
+                name: "bob".into(),
+                avatar: None,
+                bio: String::new(),
+            },
+        )
+        .await;
+        alice
+            .set_privacy(Privacy {
+                name: Visibility::Public,
+                avatar: Visibility::Friends,
+                bio: Visibility::Friends,
             })
+            .await
+            .unwrap();
+        let alice_id = alice_router.endpoint().id();
+        let (_, version) = alice.profile();
+
+        let person = bob.request_person(alice_id).await.unwrap();
+        assert_eq!(person.name, "alice");
+        assert!(person.avatar.is_none());
+        assert!(person.bio.is_empty());
+        let public = bob.request_profile(alice_id, 0).await.unwrap().unwrap();
+        public.verify(alice_id).unwrap();
+        assert_eq!(public.version, version);
+        assert_eq!(public.audience, Audience::Public);
+        assert!(public.person.bio.is_empty());
+
+        alice
+            .policy()
+            .add_friend(bob_router.endpoint().id())
+            .await
+            .unwrap();
+        let friends = bob.request_profile(alice_id, 0).await.unwrap().unwrap();
+        friends.verify(alice_id).unwrap();
+        assert_eq!(friends.version, version);
+        assert_eq!(friends.audience, Audience::Friends);
+        assert_eq!(friends.person.bio, "secret");
+        assert!(friends.person.avatar.is_some());
+
+        alice_router.shutdown().await.unwrap();
+        bob_router.shutdown().await.unwrap();
+    }
+
+    #[test]
+    fn friend_intro_signature_covers_person_and_greeting() {
+        let secret_key = SecretKey::generate(&mut rand::rng());
+        let remote_id = SecretKey::generate(&mut rand::rng()).public();
+        let intro = FriendIntro::new(
+            &secret_key,
+            remote_id,
+            Person {
+                name: "alice".into(),
+                avatar: None,
+                bio: String::new(),
+            },
+            Some("hi".into()),
+        )
+        .unwrap();
+        intro.verify(secret_key.public(), remote_id).unwrap();
+
+        let mut tampered = intro.clone();
+        tampered.person.name = "mallory".into();
+        assert!(tampered.verify(secret_key.public(), remote_id).is_err());
+
+        let mut tampered = intro.clone();
+        tampered.person.name = "alicehi".into();
+        tampered.greeting = Some(String::new());
+        assert!(tampered.verify(secret_key.public(), remote_id).is_err());
+
+        let mut tampered = intro;
+        tampered.greeting = None;
+        assert!(tampered.verify(secret_key.public(), remote_id).is_err());
     }
 }
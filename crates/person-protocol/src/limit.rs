@@ -0,0 +1,319 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+};
+
+use iroh::EndpointId;
+use n0_future::time::Instant;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::Request;
+
+const MAX_BUCKETS: usize = 4096;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub enum OverflowPolicy {
+    #[default]
+    DropOldest,
+    RejectNew,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct Limits {
+    pub person: RateLimit,
+    pub friend: RateLimit,
+    pub chat: RateLimit,
+    pub max_requests: usize,
+    pub max_streams: usize,
+    pub event_capacity: usize,
+    pub overflow: OverflowPolicy,
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            person: RateLimit {
+                burst: 10,
+                per_minute: 60,
+            },
+            friend: RateLimit {
+                burst: 3,
+                per_minute: 6,
+            },
+            chat: RateLimit {
+                burst: 5,
+                per_minute: 20,
+            },
+            max_requests: 256,
+            max_streams: 16,
+            event_capacity: 32,
+            overflow: Default::default(),
+        }
+    }
+}
+impl Limits {
+    fn rate_limit(&self, request: Request) -> RateLimit {
+        match request {
+            Request::Person
+            | Request::FriendStatus
+            | Request::PersonIfNewer
+            | Request::PersonUpdate
+            | Request::GroupKey => self.person,
+            Request::Friend | Request::Unfriend => self.friend,
+            Request::Chat | Request::GroupInvite => self.chat,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct Metrics {
+    pub rate_limited: u64,
+    pub requests_rejected: u64,
+    pub streams_rejected: u64,
+    pub events_dropped: u64,
+    pub events_rejected: u64,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    rate_limited: AtomicU64,
+    requests_rejected: AtomicU64,
+    streams_rejected: AtomicU64,
+    events_dropped: AtomicU64,
+    events_rejected: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+impl Bucket {
+    fn tokens_at(&self, rate_limit: RateLimit, now: Instant) -> f64 {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * rate_limit.per_minute as f64 / 60.0).min(rate_limit.burst as f64)
+    }
+    fn refill(&mut self, rate_limit: RateLimit, now: Instant) {
+        self.tokens = self.tokens_at(rate_limit, now);
+        self.updated = now;
+    }
+}
+
+pub struct Permit(Arc<AtomicUsize>);
+impl Permit {
+    fn acquire(counter: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        counter
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |v| {
+                (v < max).then_some(v + 1)
+            })
+            .ok()?;
+        Some(Self(counter.clone()))
+    }
+}
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Limiter {
+    limits: Limits,
+    buckets: Arc<Mutex<HashMap<(EndpointId, Request), Bucket>>>,
+    requests: Arc<AtomicUsize>,
+    counters: Arc<Counters>,
+}
+impl Limiter {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            buckets: Default::default(),
+            requests: Default::default(),
+            counters: Default::default(),
+        }
+    }
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+    pub fn check(&self, id: EndpointId, request: Request) -> bool {
+        let rate_limit = self.limits.rate_limit(request);
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock();
+        if buckets.len() >= MAX_BUCKETS {
+            buckets.retain(|(_, request), bucket| {
+                let rate_limit = self.limits.rate_limit(*request);
+                bucket.tokens_at(rate_limit, now) < rate_limit.burst as f64
+            });
+            if buckets.len() >= MAX_BUCKETS
+                && let Some(key) = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.updated)
+                    .map(|(key, _)| *key)
+            {
+                buckets.remove(&key);
+            }
+        }
+        let bucket = buckets.entry((id, request)).or_insert(Bucket {
+            tokens: rate_limit.burst as f64,
+            updated: now,
+        });
+        bucket.refill(rate_limit, now);
+        if bucket.tokens < 1.0 {
+            self.counters.rate_limited.fetch_add(1, Ordering::Relaxed);
+            log::warn!("{}的{:?}请求超出频率限制，已丢弃", id, request);
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+    pub fn acquire_request(&self) -> Option<Permit> {
+        let permit = Permit::acquire(&self.requests, self.limits.max_requests);
+        if permit.is_none() {
+            self.counters
+                .requests_rejected
+                .fetch_add(1, Ordering::Relaxed);
+            log::warn!(
+                "处理中的请求数已达上限{}，拒绝新请求",
+                self.limits.max_requests
+            );
+        }
+        permit
+    }
+    pub fn acquire_stream(&self, id: EndpointId, streams: &Arc<AtomicUsize>) -> Option<Permit> {
+        let permit = Permit::acquire(streams, self.limits.max_streams);
+        if permit.is_none() {
+            self.counters
+                .streams_rejected
+                .fetch_add(1, Ordering::Relaxed);
+            log::warn!(
+                "{}的并发流数已达上限{}，拒绝新流",
+                id,
+                self.limits.max_streams
+            );
+        }
+        permit
+    }
+    pub fn record_dropped(&self) {
+        self.counters.events_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn record_rejected(&self) {
+        self.counters
+            .events_rejected
+            .fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            rate_limited: self.counters.rate_limited.load(Ordering::Relaxed),
+            requests_rejected: self.counters.requests_rejected.load(Ordering::Relaxed),
+            streams_rejected: self.counters.streams_rejected.load(Ordering::Relaxed),
+            events_dropped: self.counters.events_dropped.load(Ordering::Relaxed),
+            events_rejected: self.counters.events_rejected.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use iroh::SecretKey;
+
+    use super::*;
+
+    fn id(n: u64) -> EndpointId {
+        let mut bytes = [0; 32];
+        bytes[..8].copy_from_slice(&n.to_be_bytes());
+        SecretKey::from_bytes(&bytes).public()
+    }
+
+    fn limiter(burst: u32, per_minute: u32) -> Limiter {
+        Limiter::new(Limits {
+            person: RateLimit { burst, per_minute },
+            max_requests: 3,
+            max_streams: 2,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn bucket_allows_burst_then_limits() {
+        let limiter = limiter(2, 1);
+        assert!(limiter.check(id(0), Request::Person));
+        assert!(limiter.check(id(0), Request::Person));
+        assert!(!limiter.check(id(0), Request::Person));
+        assert!(limiter.check(id(1), Request::Person));
+        assert!(limiter.check(id(0), Request::Friend));
+        assert_eq!(limiter.metrics().rate_limited, 1);
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let limiter = limiter(1, 6000);
+        assert!(limiter.check(id(0), Request::Person));
+        assert!(!limiter.check(id(0), Request::Person));
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(limiter.check(id(0), Request::Person));
+    }
+
+    #[test]
+    fn evicts_full_buckets_at_max_buckets() {
+        let limiter = limiter(1, 60_000);
+        for n in 0..MAX_BUCKETS as u64 {
+            limiter.check(id(n), Request::Person);
+        }
+        assert_eq!(limiter.buckets.lock().len(), MAX_BUCKETS);
+        std::thread::sleep(Duration::from_millis(20));
+        limiter.check(id(MAX_BUCKETS as u64), Request::Person);
+        assert_eq!(limiter.buckets.lock().len(), 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_updated_bucket_when_none_are_full() {
+        let limiter = limiter(1, 1);
+        for n in 0..MAX_BUCKETS as u64 {
+            assert!(limiter.check(id(n), Request::Person));
+        }
+        assert!(!limiter.check(id(1), Request::Person));
+        assert!(limiter.check(id(MAX_BUCKETS as u64), Request::Person));
+        let buckets = limiter.buckets.lock();
+        assert_eq!(buckets.len(), MAX_BUCKETS);
+        assert!(!buckets.contains_key(&(id(0), Request::Person)));
+        assert!(buckets.contains_key(&(id(1), Request::Person)));
+    }
+
+    #[test]
+    fn limits_concurrent_streams() {
+        let limiter = limiter(1, 1);
+        let streams = Arc::new(AtomicUsize::new(0));
+        let first = limiter.acquire_stream(id(0), &streams);
+        let second = limiter.acquire_stream(id(0), &streams);
+        assert!(first.is_some() && second.is_some());
+        assert!(limiter.acquire_stream(id(0), &streams).is_none());
+        drop(first);
+        assert!(limiter.acquire_stream(id(0), &streams).is_some());
+        assert_eq!(limiter.metrics().streams_rejected, 1);
+    }
+
+    #[test]
+    fn limits_in_flight_requests() {
+        let limiter = limiter(1, 1);
+        let permits = (0..3)
+            .map(|_| limiter.acquire_request())
+            .collect::<Option<Vec<_>>>();
+        assert!(permits.is_some());
+        assert!(limiter.acquire_request().is_none());
+        drop(permits);
+        assert!(limiter.acquire_request().is_some());
+        assert_eq!(limiter.metrics().requests_rejected, 1);
+    }
+}
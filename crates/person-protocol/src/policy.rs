@@ -0,0 +1,143 @@
+use std::{collections::BTreeSet, fmt::Debug, sync::Arc};
+
+use eyre::Result;
+use iroh::EndpointId;
+use n0_future::boxed::BoxFuture;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::privacy::Privacy;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct PolicyList {
+    pub blocked: BTreeSet<EndpointId>,
+    pub allowed: BTreeSet<EndpointId>,
+    pub allow_only: bool,
+    pub friends: BTreeSet<EndpointId>,
+    pub privacy: Privacy,
+    pub quota: Option<u64>,
+}
+impl PolicyList {
+    pub fn is_allowed(&self, id: &EndpointId) -> bool {
+        !self.blocked.contains(id) && (!self.allow_only || self.allowed.contains(id))
+    }
+}
+
+pub trait PolicyStore: Debug + Send + Sync {
+    fn load(&self) -> Result<PolicyList>;
+    fn save(&self, list: PolicyList) -> BoxFuture<Result<()>>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryPolicyStore;
+impl PolicyStore for MemoryPolicyStore {
+    fn load(&self) -> Result<PolicyList> {
+        Ok(Default::default())
+    }
+    fn save(&self, _: PolicyList) -> BoxFuture<Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Policy {
+    list: Arc<Mutex<PolicyList>>,
+    store: Arc<dyn PolicyStore>,
+    writer: Arc<futures::lock::Mutex<()>>,
+}
+impl Policy {
+    pub fn new(store: Arc<dyn PolicyStore>) -> Result<Self> {
+        Ok(Self {
+            list: Arc::new(Mutex::new(store.load()?)),
+            store,
+            writer: Default::default(),
+        })
+    }
+    pub fn memory() -> Self {
+        Self {
+            list: Default::default(),
+            store: Arc::new(MemoryPolicyStore),
+            writer: Default::default(),
+        }
+    }
+    async fn update(&self, f: impl FnOnce(&mut PolicyList)) -> Result<()> {
+        let _writer = self.writer.lock().await;
+        let mut next = self.list.lock().clone();
+        f(&mut next);
+        self.store.save(next.clone()).await?;
+        *self.list.lock() = next;
+        Ok(())
+    }
+    pub fn is_allowed(&self, id: &EndpointId) -> bool {
+        self.list.lock().is_allowed(id)
+    }
+    pub async fn block(&self, id: EndpointId) -> Result<()> {
+        self.update(|v| {
+            v.allowed.remove(&id);
+            v.blocked.insert(id);
+        })
+        .await
+    }
+    pub async fn unblock(&self, id: EndpointId) -> Result<()> {
+        self.update(|v| {
+            v.blocked.remove(&id);
+        })
+        .await
+    }
+    pub async fn allow(&self, id: EndpointId) -> Result<()> {
+        self.update(|v| {
+            v.blocked.remove(&id);
+            v.allowed.insert(id);
+        })
+        .await
+    }
+    pub async fn disallow(&self, id: EndpointId) -> Result<()> {
+        self.update(|v| {
+            v.allowed.remove(&id);
+        })
+        .await
+    }
+    pub fn allow_only(&self) -> bool {
+        self.list.lock().allow_only
+    }
+    pub async fn set_allow_only(&self, allow_only: bool) -> Result<()> {
+        self.update(|v| v.allow_only = allow_only).await
+    }
+    pub async fn add_friend(&self, id: EndpointId) -> Result<()> {
+        self.update(|v| {
+            v.friends.insert(id);
+        })
+        .await
+    }
+    pub async fn remove_friend(&self, id: EndpointId) -> Result<()> {
+        self.update(|v| {
+            v.friends.remove(&id);
+        })
+        .await
+    }
+    pub fn is_friend(&self, id: &EndpointId) -> bool {
+        self.list.lock().friends.contains(id)
+    }
+    pub fn friends(&self) -> Vec<EndpointId> {
+        self.list.lock().friends.iter().copied().collect()
+    }
+    pub fn privacy(&self) -> Privacy {
+        self.list.lock().privacy
+    }
+    pub async fn set_privacy(&self, privacy: Privacy) -> Result<()> {
+        self.update(|v| v.privacy = privacy).await
+    }
+    pub fn quota(&self) -> Option<u64> {
+        self.list.lock().quota
+    }
+    pub async fn set_quota(&self, quota: Option<u64>) -> Result<()> {
+        self.update(|v| v.quota = quota).await
+    }
+    pub fn blocked(&self) -> Vec<EndpointId> {
+        self.list.lock().blocked.iter().copied().collect()
+    }
+    pub fn allowed(&self) -> Vec<EndpointId> {
+        self.list.lock().allowed.iter().copied().collect()
+    }
+}
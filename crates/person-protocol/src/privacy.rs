@@ -0,0 +1,61 @@
+use rkyv::Archive;
+use serde::{Deserialize, Serialize};
+
+use crate::Person;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Visibility {
+    #[default]
+    Public,
+    Friends,
+    Nobody,
+}
+impl Visibility {
+    pub fn is_visible(self, audience: Audience) -> bool {
+        match self {
+            Visibility::Public => true,
+            Visibility::Friends => audience == Audience::Friends,
+            Visibility::Nobody => false,
+        }
+    }
+}
+
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Audience {
+    Public,
+    Friends,
+}
+impl Audience {
+    pub fn of(is_friend: bool) -> Self {
+        if is_friend {
+            Audience::Friends
+        } else {
+            Audience::Public
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Privacy {
+    pub name: Visibility,
+    pub avatar: Visibility,
+    pub bio: Visibility,
+}
+impl Privacy {
+    pub fn redact(&self, person: &Person, audience: Audience) -> Person {
+        Person {
+            name: if self.name.is_visible(audience) {
+                person.name.clone()
+            } else {
+                String::new()
+            },
+            avatar: person.avatar.filter(|_| self.avatar.is_visible(audience)),
+            bio: if self.bio.is_visible(audience) {
+                person.bio.clone()
+            } else {
+                String::new()
+            },
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::HashMap, sync::Arc};
+
+use eyre::{Result, bail, eyre};
+use iroh::endpoint::{RecvStream, SendStream};
+use parking_lot::Mutex;
+use rkyv::Archive;
+
+use crate::{AVATAR_MAX_SIZE, Avatar, REQUEST_MAX_SIZE, frame};
+
+pub const ALPN: &[u8] = b"person/v1";
+pub const RESPONSE_MAX_SIZE: usize = AVATAR_MAX_SIZE as usize + 64 * 1024;
+
+const RECEIVED_AVATARS_MAX: usize = 16;
+
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Debug)]
+pub enum Request {
+    Person,
+    Friend,
+    Chat,
+}
+impl TryFrom<&crate::Request> for Request {
+    type Error = eyre::Report;
+
+    fn try_from(request: &crate::Request) -> Result<Self> {
+        Ok(match request {
+            crate::Request::Person => Request::Person,
+            crate::Request::Friend => Request::Friend,
+            crate::Request::Chat => Request::Chat,
+            crate::Request::Unfriend
+            | crate::Request::FriendStatus
+            | crate::Request::PersonIfNewer
+            | crate::Request::PersonUpdate
+            | crate::Request::GroupInvite
+            | crate::Request::GroupKey => {
+                bail!("对方版本过旧，不支持该请求")
+            }
+        })
+    }
+}
+impl From<Request> for crate::Request {
+    fn from(request: Request) -> Self {
+        match request {
+            Request::Person => crate::Request::Person,
+            Request::Friend => crate::Request::Friend,
+            Request::Chat => crate::Request::Chat,
+        }
+    }
+}
+
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Debug)]
+pub enum Response {
+    Person(Person),
+    Friend(bool),
+    Chat(bool),
+}
+impl Response {
+    fn new(response: crate::Response, avatars: &Avatars) -> Result<Self> {
+        Ok(match response {
+            crate::Response::Person(person) => Response::Person(Person::new(person, avatars)),
+            crate::Response::Friend(result) => Response::Friend(result),
+            crate::Response::Chat(result) => Response::Chat(result),
+            crate::Response::Expired
+            | crate::Response::Ack
+            | crate::Response::FriendStatus(_)
+            | crate::Response::Unchanged
+            | crate::Response::Profile(_)
+            | crate::Response::GroupInvite(_)
+            | crate::Response::GroupKey(_) => {
+                bail!("旧版协议不支持该响应")
+            }
+        })
+    }
+    fn into_response(self, avatars: &Avatars) -> crate::Response {
+        match self {
+            Response::Person(person) => crate::Response::Person(person.into_person(avatars)),
+            Response::Friend(result) => crate::Response::Friend(result),
+            Response::Chat(result) => crate::Response::Chat(result),
+        }
+    }
+}
+
+#[derive(Archive, rkyv::Serialize, rkyv::Deserialize, Debug)]
+pub struct Person {
+    pub name: String,
+    pub avatar: Option<Vec<u8>>,
+    pub bio: String,
+}
+impl Person {
+    fn new(person: crate::Person, avatars: &Avatars) -> Self {
+        Self {
+            name: person.name,
+            avatar: person.avatar.and_then(|v| avatars.local(&v.hash)),
+            bio: person.bio,
+        }
+    }
+    fn into_person(self, avatars: &Avatars) -> crate::Person {
+        crate::Person {
+            name: self.name,
+            avatar: self.avatar.and_then(|v| avatars.receive(v)),
+            bio: self.bio,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LocalAvatar {
+    avatar: Avatar,
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Avatars {
+    local: Arc<Mutex<Option<LocalAvatar>>>,
+    received: Arc<Mutex<HashMap<[u8; 32], Vec<u8>>>>,
+}
+impl Avatars {
+    pub fn set_local(&self, avatar: Option<Vec<u8>>) {
+        *self.local.lock() = avatar.map(|v| LocalAvatar {
+            avatar: Avatar {
+                hash: *blake3::hash(&v).as_bytes(),
+                size: v.len() as u64,
+            },
+            bytes: v,
+        });
+    }
+    pub fn take(&self, hash: &[u8; 32]) -> Option<Vec<u8>> {
+        self.received.lock().remove(hash)
+    }
+    fn local(&self, hash: &[u8; 32]) -> Option<Vec<u8>> {
+        self.local
+            .lock()
+            .as_ref()
+            .filter(|v| v.avatar.hash == *hash)
+            .map(|v| v.bytes.clone())
+    }
+    fn receive(&self, bytes: Vec<u8>) -> Option<Avatar> {
+        let avatar = Avatar {
+            hash: *blake3::hash(&bytes).as_bytes(),
+            size: bytes.len() as u64,
+        };
+        if avatar.size > AVATAR_MAX_SIZE {
+            return None;
+        }
+        let mut received = self.received.lock();
+        if received.len() >= RECEIVED_AVATARS_MAX
+            && let Some(hash) = received.keys().next().copied()
+        {
+            received.remove(&hash);
+        }
+        received.insert(avatar.hash, bytes);
+        Some(avatar)
+    }
+}
+
+pub(crate) async fn exchange(
+    (mut send, mut recv): (SendStream, RecvStream),
+    request: crate::Request,
+    avatars: &Avatars,
+) -> Result<crate::Response> {
+    send.write_all(&frame::encode(&Request::try_from(&request)?)?)
+        .await?;
+    send.finish()?;
+    let bytes = recv.read_to_end(RESPONSE_MAX_SIZE).await?;
+    Ok(frame::decode::<Response>(&bytes)?.into_response(avatars))
+}
+
+pub(crate) async fn read_request(recv: &mut RecvStream) -> Result<crate::Request> {
+    let bytes = n0_future::time::timeout(frame::READ_TIMEOUT, recv.read_to_end(REQUEST_MAX_SIZE))
+        .await
+        .map_err(|_| eyre!("读取请求超时"))??;
+    Ok(frame::decode::<Request>(&bytes)?.into())
+}
+
+pub(crate) async fn write_response(
+    send: &mut SendStream,
+    response: crate::Response,
+    avatars: &Avatars,
+) -> Result<()> {
+    send.write_all(&frame::encode(&Response::new(response, avatars)?)?)
+        .await?;
+    send.finish()?;
+    Ok(())
+}
@@ -1,7 +1,9 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use endpoint::Endpoint;
+use endpoint::{Endpoint, PolicyOption, StoreOption};
 use sharded_slab::Slab;
+use tauri::{Manager, Runtime, Window, ipc::Channel};
+use tokio::{sync::Mutex, task::AbortHandle};
 use utils::option_ext::OptionGet;
 
 use crate::router::error::MapStringError;
@@ -10,26 +12,138 @@ use crate::router::error::MapStringError;
 pub trait EndpointApi {
     async fn generate_secret_key() -> Vec<u8>;
     async fn get_secret_key_id(secret_key: Vec<u8>) -> Result<String, String>;
-    async fn generate_group_id() -> String;
-    async fn generate_ticket(group_id: String, bootstrap: Vec<String>) -> Result<String, String>;
-    async fn open_endpoint(secret_key: Vec<u8>, person: serde_json::Value)
-    -> Result<usize, String>;
+    async fn parse_ticket(ticket: String) -> Result<serde_json::Value, String>;
+    async fn open_endpoint<R: Runtime>(
+        window: Window<R>,
+        secret_key: Vec<u8>,
+        person: serde_json::Value,
+    ) -> Result<usize, String>;
     async fn close_endpoint(handle: usize) -> Result<(), String>;
     async fn id(handle: usize) -> Result<String, String>;
-    async fn person_protocol_next_event(handle: usize) -> Result<String, String>;
-    async fn person_protocol_event(
+    async fn on_person_protocol_event(
         handle: usize,
-        method: String,
-    ) -> Result<serde_json::Value, String>;
+        channel: Channel<serde_json::Value>,
+    ) -> Result<(), String>;
+    async fn respond(
+        handle: usize,
+        event_id: usize,
+        response: serde_json::Value,
+    ) -> Result<Option<usize>, String>;
     async fn request_person(handle: usize, id: String) -> Result<serde_json::Value, String>;
-    async fn request_friend(handle: usize, id: String) -> Result<bool, String>;
-    async fn request_chat(handle: usize, id: String) -> Result<Option<usize>, String>;
+    async fn request_profile(
+        handle: usize,
+        id: String,
+        version: u64,
+    ) -> Result<serde_json::Value, String>;
+    async fn profile(handle: usize) -> Result<serde_json::Value, String>;
+    async fn update_person(handle: usize, person: serde_json::Value) -> Result<(), String>;
+    async fn fetch_avatar(
+        handle: usize,
+        id: String,
+        hash: String,
+        size: u64,
+    ) -> Result<Vec<u8>, String>;
+    async fn request_friend(
+        handle: usize,
+        id: String,
+        greeting: Option<String>,
+    ) -> Result<serde_json::Value, String>;
+    async fn request_chat(handle: usize, id: String) -> Result<serde_json::Value, String>;
+    async fn send_message(
+        handle: usize,
+        chat_handle: usize,
+        payload: Vec<u8>,
+    ) -> Result<(), String>;
+    async fn next_message(handle: usize, chat_handle: usize) -> Result<Option<Vec<u8>>, String>;
+    async fn close_chat(handle: usize, chat_handle: usize) -> Result<(), String>;
+    async fn create_group_ticket(
+        handle: usize,
+        group_handle: usize,
+        options: serde_json::Value,
+    ) -> Result<String, String>;
+    async fn create_group(handle: usize, name: String) -> Result<usize, String>;
     async fn subscribe_group(handle: usize, ticket: String) -> Result<usize, String>;
+    async fn invite_to_group(
+        handle: usize,
+        friend_id: String,
+        group_handle: usize,
+    ) -> Result<serde_json::Value, String>;
+    async fn group_broadcast(
+        handle: usize,
+        group_handle: usize,
+        payload: Vec<u8>,
+    ) -> Result<(), String>;
+    async fn group_next_event(
+        handle: usize,
+        group_handle: usize,
+    ) -> Result<Option<serde_json::Value>, String>;
+    async fn group_membership(
+        handle: usize,
+        group_handle: usize,
+    ) -> Result<Option<serde_json::Value>, String>;
+    async fn manage_group(
+        handle: usize,
+        group_handle: usize,
+        action: serde_json::Value,
+    ) -> Result<(), String>;
+    async fn group_neighbors(handle: usize, group_handle: usize) -> Result<Vec<String>, String>;
+    async fn leave_group(handle: usize, group_handle: usize) -> Result<(), String>;
+    async fn block(handle: usize, id: String) -> Result<(), String>;
+    async fn unblock(handle: usize, id: String) -> Result<(), String>;
+    async fn list_blocked(handle: usize) -> Result<Vec<String>, String>;
+    async fn allow(handle: usize, id: String) -> Result<(), String>;
+    async fn disallow(handle: usize, id: String) -> Result<(), String>;
+    async fn list_allowed(handle: usize) -> Result<Vec<String>, String>;
+    async fn allow_only(handle: usize) -> Result<bool, String>;
+    async fn set_allow_only(handle: usize, allow_only: bool) -> Result<(), String>;
+    async fn protocol_metrics(handle: usize) -> Result<serde_json::Value, String>;
+    async fn unfriend(handle: usize, id: String) -> Result<(), String>;
+    async fn friend_status(handle: usize, id: String) -> Result<bool, String>;
+    async fn add_friend(handle: usize, id: String) -> Result<(), String>;
+    async fn list_friends(handle: usize) -> Result<Vec<String>, String>;
+    async fn privacy(handle: usize) -> Result<serde_json::Value, String>;
+    async fn share_file(handle: usize, path: String) -> Result<serde_json::Value, String>;
+    async fn share_bytes(handle: usize, bytes: Vec<u8>) -> Result<serde_json::Value, String>;
+    async fn download(
+        handle: usize,
+        id: String,
+        hash: String,
+        dest: Option<String>,
+        channel: Channel<serde_json::Value>,
+    ) -> Result<usize, String>;
+    async fn share_directory(handle: usize, path: String) -> Result<serde_json::Value, String>;
+    async fn list_collection(
+        handle: usize,
+        id: String,
+        hash: String,
+    ) -> Result<serde_json::Value, String>;
+    async fn download_collection(
+        handle: usize,
+        id: String,
+        hash: String,
+        entries: Option<Vec<usize>>,
+        dest: Option<String>,
+        channel: Channel<serde_json::Value>,
+    ) -> Result<usize, String>;
+    async fn cancel_transfer(handle: usize, transfer_handle: usize) -> Result<(), String>;
+    async fn read_blob(handle: usize, hash: String) -> Result<Vec<u8>, String>;
+    async fn tag_blob(handle: usize, hash: String, owner: serde_json::Value) -> Result<(), String>;
+    async fn untag_blob(
+        handle: usize,
+        hash: String,
+        owner: serde_json::Value,
+    ) -> Result<(), String>;
+    async fn delete_blob(handle: usize, hash: String) -> Result<(), String>;
+    async fn clear_cache(handle: usize) -> Result<(), String>;
+    async fn storage_usage(handle: usize) -> Result<serde_json::Value, String>;
+    async fn set_storage_quota(handle: usize, quota: Option<u64>) -> Result<(), String>;
+    async fn set_privacy(handle: usize, privacy: serde_json::Value) -> Result<(), String>;
 }
 
 #[derive(Clone, Default)]
 pub struct EndpointApiImpl {
     endpoint_pool: Arc<Slab<Endpoint>>,
+    event_forwarders: Arc<Mutex<HashMap<usize, AbortHandle>>>,
 }
 #[taurpc::resolvers]
 impl EndpointApi for EndpointApiImpl {
@@ -39,25 +153,35 @@ impl EndpointApi for EndpointApiImpl {
     async fn get_secret_key_id(self, secret_key: Vec<u8>) -> Result<String, String> {
         endpoint::get_secret_key_id(secret_key).mse()
     }
-    async fn generate_group_id(self) -> String {
-        endpoint::generate_group_id()
-    }
-    async fn generate_ticket(
-        self,
-        group_id: String,
-        bootstrap: Vec<String>,
-    ) -> Result<String, String> {
-        endpoint::generate_ticket(group_id, bootstrap).mse()
+    async fn parse_ticket(self, ticket: String) -> Result<serde_json::Value, String> {
+        async { eyre::Ok(serde_json::to_value(endpoint::parse_ticket(ticket)?)?) }
+            .await
+            .mse()
     }
-    async fn open_endpoint(
+    async fn open_endpoint<R: Runtime>(
         self,
+        window: Window<R>,
         secret_key: Vec<u8>,
         person: serde_json::Value,
     ) -> Result<usize, String> {
         async {
+            let app_data_dir = window.path().app_data_dir()?;
+            let secret_key_id = endpoint::get_secret_key_id(secret_key.clone())?;
             eyre::Ok(
                 self.endpoint_pool
-                    .insert(Endpoint::new(secret_key, serde_json::from_value(person)?).await?)
+                    .insert(
+                        Endpoint::builder(secret_key, serde_json::from_value(person)?)
+                            .store(StoreOption::Fs(
+                                app_data_dir.join("store").join(&secret_key_id),
+                            ))
+                            .policy(PolicyOption::Fs(
+                                app_data_dir
+                                    .join("policy")
+                                    .join(format!("{}.json", secret_key_id)),
+                            ))
+                            .bind()
+                            .await?,
+                    )
                     .get()?,
             )
         }
@@ -66,6 +190,9 @@ impl EndpointApi for EndpointApiImpl {
     }
     async fn close_endpoint(self, handle: usize) -> Result<(), String> {
         async {
+            if let Some(forwarder) = self.event_forwarders.lock().await.remove(&handle) {
+                forwarder.abort();
+            }
             if let Some(endpoint) = self.endpoint_pool.take(handle) {
                 endpoint.close().await?;
             }
@@ -77,71 +204,648 @@ impl EndpointApi for EndpointApiImpl {
     async fn id(self, handle: usize) -> Result<String, String> {
         Ok(self.endpoint_pool.get(handle).get().mse()?.id())
     }
-    async fn person_protocol_next_event(self, handle: usize) -> Result<String, String> {
+    async fn on_person_protocol_event(
+        self,
+        handle: usize,
+        channel: Channel<serde_json::Value>,
+    ) -> Result<(), String> {
+        let endpoint = self.endpoint_pool.get(handle).get().mse()?.clone();
+        let mut event_forwarders = self.event_forwarders.lock().await;
+        if let Some(forwarder) = event_forwarders.remove(&handle) {
+            forwarder.abort();
+        }
+        let forwarder = tokio::spawn(async move {
+            loop {
+                if let Err(err) = async {
+                    channel.send(serde_json::to_value(endpoint.next_event().await?)?)?;
+                    eyre::Ok(())
+                }
+                .await
+                {
+                    log::error!("{}", err);
+                    break;
+                }
+            }
+        });
+        event_forwarders.insert(handle, forwarder.abort_handle());
+        Ok(())
+    }
+    async fn respond(
+        self,
+        handle: usize,
+        event_id: usize,
+        response: serde_json::Value,
+    ) -> Result<Option<usize>, String> {
+        async {
+            self.endpoint_pool
+                .get_owned(handle)
+                .get()?
+                .respond(event_id, serde_json::from_value(response)?)
+                .await
+        }
+        .await
+        .mse()
+    }
+    async fn request_person(self, handle: usize, id: String) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                &self
+                    .endpoint_pool
+                    .get_owned(handle)
+                    .get()?
+                    .request_person(id)
+                    .await?,
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn request_profile(
+        self,
+        handle: usize,
+        id: String,
+        version: u64,
+    ) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                &self
+                    .endpoint_pool
+                    .get_owned(handle)
+                    .get()?
+                    .request_profile(id, version)
+                    .await?,
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn profile(self, handle: usize) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                self.endpoint_pool.get(handle).get()?.profile(),
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn update_person(self, handle: usize, person: serde_json::Value) -> Result<(), String> {
+        async {
+            self.endpoint_pool
+                .get_owned(handle)
+                .get()?
+                .update_person(serde_json::from_value(person)?)
+                .await
+        }
+        .await
+        .mse()
+    }
+    async fn fetch_avatar(
+        self,
+        handle: usize,
+        id: String,
+        hash: String,
+        size: u64,
+    ) -> Result<Vec<u8>, String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .fetch_avatar(id, hash, size)
+            .await
+            .mse()
+    }
+    async fn request_friend(
+        self,
+        handle: usize,
+        id: String,
+        greeting: Option<String>,
+    ) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                &self
+                    .endpoint_pool
+                    .get_owned(handle)
+                    .get()?
+                    .request_friend(id, greeting)
+                    .await?,
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn request_chat(self, handle: usize, id: String) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                &self
+                    .endpoint_pool
+                    .get_owned(handle)
+                    .get()?
+                    .request_chat(id)
+                    .await?,
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn send_message(
+        self,
+        handle: usize,
+        chat_handle: usize,
+        payload: Vec<u8>,
+    ) -> Result<(), String> {
         Ok(self
             .endpoint_pool
             .get_owned(handle)
             .get()
             .mse()?
-            .person_protocol_next_event()
+            .send_message(chat_handle, payload)
             .await
             .mse()?)
     }
-    async fn person_protocol_event(
+    async fn next_message(
         self,
         handle: usize,
-        method: String,
-    ) -> Result<serde_json::Value, String> {
+        chat_handle: usize,
+    ) -> Result<Option<Vec<u8>>, String> {
         Ok(self
             .endpoint_pool
-            .get(handle)
+            .get_owned(handle)
             .get()
             .mse()?
-            .person_protocol_event(method)
+            .next_message(chat_handle)
+            .await
             .mse()?)
     }
-    async fn request_person(self, handle: usize, id: String) -> Result<serde_json::Value, String> {
+    async fn close_chat(self, handle: usize, chat_handle: usize) -> Result<(), String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .close_chat(chat_handle)
+            .await;
+        Ok(())
+    }
+    async fn create_group_ticket(
+        self,
+        handle: usize,
+        group_handle: usize,
+        options: serde_json::Value,
+    ) -> Result<String, String> {
+        async {
+            self.endpoint_pool
+                .get(handle)
+                .get()?
+                .create_group_ticket(group_handle, serde_json::from_value(options)?)
+        }
+        .await
+        .mse()
+    }
+    async fn create_group(self, handle: usize, name: String) -> Result<usize, String> {
+        Ok(self
+            .endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .create_group(name)
+            .await
+            .mse()?)
+    }
+    async fn subscribe_group(self, handle: usize, ticket: String) -> Result<usize, String> {
+        Ok(self
+            .endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .subscribe_group(ticket)
+            .await
+            .mse()?)
+    }
+    async fn invite_to_group(
+        self,
+        handle: usize,
+        friend_id: String,
+        group_handle: usize,
+    ) -> Result<serde_json::Value, String> {
         async {
             eyre::Ok(serde_json::to_value(
                 &self
                     .endpoint_pool
                     .get_owned(handle)
                     .get()?
-                    .request_person(id)
+                    .invite_to_group(friend_id, group_handle)
                     .await?,
             )?)
         }
         .await
         .mse()
     }
-    async fn request_friend(self, handle: usize, id: String) -> Result<bool, String> {
+    async fn group_broadcast(
+        self,
+        handle: usize,
+        group_handle: usize,
+        payload: Vec<u8>,
+    ) -> Result<(), String> {
         Ok(self
             .endpoint_pool
             .get_owned(handle)
             .get()
             .mse()?
-            .request_friend(id)
+            .group_broadcast(group_handle, payload)
             .await
             .mse()?)
     }
-    async fn request_chat(self, handle: usize, id: String) -> Result<Option<usize>, String> {
+    async fn group_next_event(
+        self,
+        handle: usize,
+        group_handle: usize,
+    ) -> Result<Option<serde_json::Value>, String> {
+        async {
+            eyre::Ok(
+                self.endpoint_pool
+                    .get_owned(handle)
+                    .get()?
+                    .group_next_event(group_handle)
+                    .await?
+                    .map(serde_json::to_value)
+                    .transpose()?,
+            )
+        }
+        .await
+        .mse()
+    }
+    async fn group_membership(
+        self,
+        handle: usize,
+        group_handle: usize,
+    ) -> Result<Option<serde_json::Value>, String> {
+        async {
+            eyre::Ok(
+                self.endpoint_pool
+                    .get(handle)
+                    .get()?
+                    .group_membership(group_handle)?
+                    .map(serde_json::to_value)
+                    .transpose()?,
+            )
+        }
+        .await
+        .mse()
+    }
+    async fn manage_group(
+        self,
+        handle: usize,
+        group_handle: usize,
+        action: serde_json::Value,
+    ) -> Result<(), String> {
+        async {
+            self.endpoint_pool
+                .get_owned(handle)
+                .get()?
+                .manage_group(group_handle, serde_json::from_value(action)?)
+                .await
+        }
+        .await
+        .mse()
+    }
+    async fn group_neighbors(
+        self,
+        handle: usize,
+        group_handle: usize,
+    ) -> Result<Vec<String>, String> {
         Ok(self
             .endpoint_pool
+            .get(handle)
+            .get()
+            .mse()?
+            .group_neighbors(group_handle)
+            .mse()?)
+    }
+    async fn leave_group(self, handle: usize, group_handle: usize) -> Result<(), String> {
+        self.endpoint_pool
+            .get(handle)
+            .get()
+            .mse()?
+            .leave_group(group_handle);
+        Ok(())
+    }
+    async fn block(self, handle: usize, id: String) -> Result<(), String> {
+        self.endpoint_pool
             .get_owned(handle)
             .get()
             .mse()?
-            .request_chat(id)
+            .block(id)
             .await
-            .mse()?)
+            .mse()
     }
-    async fn subscribe_group(self, handle: usize, ticket: String) -> Result<usize, String> {
-        Ok(self
-            .endpoint_pool
+    async fn unblock(self, handle: usize, id: String) -> Result<(), String> {
+        self.endpoint_pool
             .get_owned(handle)
             .get()
             .mse()?
-            .subscribe_group(ticket)
+            .unblock(id)
             .await
-            .mse()?)
+            .mse()
+    }
+    async fn list_blocked(self, handle: usize) -> Result<Vec<String>, String> {
+        Ok(self.endpoint_pool.get(handle).get().mse()?.list_blocked())
+    }
+    async fn allow(self, handle: usize, id: String) -> Result<(), String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .allow(id)
+            .await
+            .mse()
     }
+    async fn disallow(self, handle: usize, id: String) -> Result<(), String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .disallow(id)
+            .await
+            .mse()
+    }
+    async fn list_allowed(self, handle: usize) -> Result<Vec<String>, String> {
+        Ok(self.endpoint_pool.get(handle).get().mse()?.list_allowed())
+    }
+    async fn allow_only(self, handle: usize) -> Result<bool, String> {
+        Ok(self.endpoint_pool.get(handle).get().mse()?.allow_only())
+    }
+    async fn set_allow_only(self, handle: usize, allow_only: bool) -> Result<(), String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .set_allow_only(allow_only)
+            .await
+            .mse()
+    }
+    async fn protocol_metrics(self, handle: usize) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                self.endpoint_pool.get(handle).get()?.protocol_metrics(),
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn unfriend(self, handle: usize, id: String) -> Result<(), String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .unfriend(id)
+            .await
+            .mse()
+    }
+    async fn friend_status(self, handle: usize, id: String) -> Result<bool, String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .friend_status(id)
+            .await
+            .mse()
+    }
+    async fn add_friend(self, handle: usize, id: String) -> Result<(), String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .add_friend(id)
+            .await
+            .mse()
+    }
+    async fn list_friends(self, handle: usize) -> Result<Vec<String>, String> {
+        Ok(self.endpoint_pool.get(handle).get().mse()?.list_friends())
+    }
+    async fn privacy(self, handle: usize) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                self.endpoint_pool.get(handle).get()?.privacy(),
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn share_file(self, handle: usize, path: String) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                self.endpoint_pool
+                    .get_owned(handle)
+                    .get()?
+                    .share_file(path.into())
+                    .await?,
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn share_bytes(self, handle: usize, bytes: Vec<u8>) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                self.endpoint_pool
+                    .get_owned(handle)
+                    .get()?
+                    .share_bytes(bytes)
+                    .await?,
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn download(
+        self,
+        handle: usize,
+        id: String,
+        hash: String,
+        dest: Option<String>,
+        channel: Channel<serde_json::Value>,
+    ) -> Result<usize, String> {
+        let endpoint = self.endpoint_pool.get(handle).get().mse()?.clone();
+        let transfer_handle = endpoint.download(id, hash, dest.map(Into::into)).mse()?;
+        forward_transfer(endpoint, transfer_handle, channel);
+        Ok(transfer_handle)
+    }
+    async fn share_directory(
+        self,
+        handle: usize,
+        path: String,
+    ) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                self.endpoint_pool
+                    .get_owned(handle)
+                    .get()?
+                    .share_directory(path.into())
+                    .await?,
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn list_collection(
+        self,
+        handle: usize,
+        id: String,
+        hash: String,
+    ) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                self.endpoint_pool
+                    .get_owned(handle)
+                    .get()?
+                    .list_collection(id, hash)
+                    .await?,
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn download_collection(
+        self,
+        handle: usize,
+        id: String,
+        hash: String,
+        entries: Option<Vec<usize>>,
+        dest: Option<String>,
+        channel: Channel<serde_json::Value>,
+    ) -> Result<usize, String> {
+        let endpoint = self.endpoint_pool.get(handle).get().mse()?.clone();
+        let transfer_handle = endpoint
+            .download_collection(id, hash, entries, dest.map(Into::into))
+            .await
+            .mse()?;
+        forward_transfer(endpoint, transfer_handle, channel);
+        Ok(transfer_handle)
+    }
+    async fn cancel_transfer(self, handle: usize, transfer_handle: usize) -> Result<(), String> {
+        self.endpoint_pool
+            .get(handle)
+            .get()
+            .mse()?
+            .cancel_transfer(transfer_handle);
+        Ok(())
+    }
+    async fn read_blob(self, handle: usize, hash: String) -> Result<Vec<u8>, String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .read_blob(hash)
+            .await
+            .mse()
+    }
+    async fn tag_blob(
+        self,
+        handle: usize,
+        hash: String,
+        owner: serde_json::Value,
+    ) -> Result<(), String> {
+        async {
+            self.endpoint_pool
+                .get_owned(handle)
+                .get()?
+                .tag_blob(hash, serde_json::from_value(owner)?)
+                .await
+        }
+        .await
+        .mse()
+    }
+    async fn untag_blob(
+        self,
+        handle: usize,
+        hash: String,
+        owner: serde_json::Value,
+    ) -> Result<(), String> {
+        async {
+            self.endpoint_pool
+                .get_owned(handle)
+                .get()?
+                .untag_blob(hash, serde_json::from_value(owner)?)
+                .await
+        }
+        .await
+        .mse()
+    }
+    async fn delete_blob(self, handle: usize, hash: String) -> Result<(), String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .delete_blob(hash)
+            .await
+            .mse()
+    }
+    async fn clear_cache(self, handle: usize) -> Result<(), String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .clear_cache()
+            .await
+            .mse()
+    }
+    async fn storage_usage(self, handle: usize) -> Result<serde_json::Value, String> {
+        async {
+            eyre::Ok(serde_json::to_value(
+                self.endpoint_pool
+                    .get_owned(handle)
+                    .get()?
+                    .storage_usage()
+                    .await?,
+            )?)
+        }
+        .await
+        .mse()
+    }
+    async fn set_storage_quota(self, handle: usize, quota: Option<u64>) -> Result<(), String> {
+        self.endpoint_pool
+            .get_owned(handle)
+            .get()
+            .mse()?
+            .set_storage_quota(quota)
+            .await
+            .mse()
+    }
+    async fn set_privacy(self, handle: usize, privacy: serde_json::Value) -> Result<(), String> {
+        async {
+            self.endpoint_pool
+                .get_owned(handle)
+                .get()?
+                .set_privacy(serde_json::from_value(privacy)?)
+                .await
+        }
+        .await
+        .mse()
+    }
+}
+
+fn forward_transfer(
+    endpoint: Endpoint,
+    transfer_handle: usize,
+    channel: Channel<serde_json::Value>,
+) {
+    tokio::spawn(async move {
+        loop {
+            match async {
+                let Some(event) = endpoint.transfer_next_event(transfer_handle).await? else {
+                    return eyre::Ok(false);
+                };
+                channel.send(serde_json::to_value(event)?)?;
+                eyre::Ok(true)
+            }
+            .await
+            {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(err) => {
+                    log::error!("{}", err);
+                    break;
+                }
+            }
+        }
+    });
 }
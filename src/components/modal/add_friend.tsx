@@ -1,24 +1,53 @@
 import { HomeContext, use_context } from "../context";
 import { tryit } from "radash";
+import { createSignal, Show } from "solid-js";
+import type { RemotePerson } from "~/lib/endpoint/types";
 
 export default function AddFriend() {
   const home_store = use_context(HomeContext);
   let search_user_id_input_ref: HTMLInputElement | undefined;
+  let greeting_input_ref: HTMLInputElement | undefined;
+  const [found, set_found] = createSignal<{
+    id: string;
+    person: RemotePerson;
+  }>();
+  const [status, set_status] = createSignal<string>();
   const on_search_user = async () => {
     if (
       search_user_id_input_ref !== undefined &&
       search_user_id_input_ref.value != ""
     ) {
+      const id = search_user_id_input_ref.value;
+      set_found();
+      set_status();
       const [err, person] = await tryit((id: string) =>
         home_store.endpoint.request_person(id),
-      )(search_user_id_input_ref.value);
+      )(id);
       if (err) {
-        console.info(err.message);
+        set_status(err.message);
       } else {
-        console.info(person);
+        set_found({ id, person });
       }
     }
   };
+  const on_request_friend = async (id: string) => {
+    const greeting = greeting_input_ref?.value || undefined;
+    set_status("等待对方回应");
+    const [err, outcome] = await tryit(
+      (id: string, greeting?: string) =>
+        home_store.endpoint.request_friend(id, greeting),
+    )(id, greeting);
+    if (err) {
+      set_status(err.message);
+    } else if (outcome.type === "Accepted") {
+      await home_store.refresh_friend(id);
+      set_status("已添加为好友");
+    } else if (outcome.type === "Rejected") {
+      set_status("对方拒绝了好友请求");
+    } else {
+      set_status("对方未回应好友请求");
+    }
+  };
   return (
     <div class="modal-box flex flex-col relative">
       <span class="absolute top-3 right-3 select-none text-base-content/60 text-sm">
@@ -45,6 +74,32 @@ export default function AddFriend() {
             </button>
           </div>
         </div>
+        <Show keyed when={found()}>
+          {(v) => (
+            <div class="flex flex-col gap-2 border border-base-300 rounded-box p-2">
+              <div class="flex flex-col">
+                <span>{v.person.name}</span>
+                <span class="text-xs text-base-content/60">{v.person.bio}</span>
+              </div>
+              <div class="join w-full">
+                <input
+                  ref={greeting_input_ref}
+                  class="join-item input flex-1"
+                  placeholder="招呼语（可选）"
+                />
+                <button
+                  class="join-item btn btn-primary"
+                  onClick={() => on_request_friend(v.id)}
+                >
+                  添加
+                </button>
+              </div>
+            </div>
+          )}
+        </Show>
+        <Show when={status()}>
+          {(v) => <span class="text-sm text-base-content/60">{v()}</span>}
+        </Show>
       </div>
     </div>
   );
@@ -1,9 +1,15 @@
-import { MessagesSquareIcon, UserIcon, UserPlusIcon } from "lucide-solid";
+import {
+  CheckIcon,
+  MessagesSquareIcon,
+  UserIcon,
+  UserPlusIcon,
+  XIcon,
+} from "lucide-solid";
 import { createSignal, For, lazy, Show, Suspense } from "solid-js";
 import { createAsync, useParams } from "@solidjs/router";
 import { QueryBuilder } from "~/lib/query_builder";
 import type { Person } from "~/lib/types";
-import { MainContext, use_context } from "../context";
+import { HomeContext, MainContext, use_context } from "../context";
 import { createVirtualizer } from "@tanstack/solid-virtual";
 import Image from "../widgets/image";
 
@@ -14,6 +20,7 @@ export default function FriendList() {
   const [lazy_add_friend_modal_load, set_lazy_add_friend_modal_load] =
     createSignal(false);
   const main_store = use_context(MainContext);
+  const home_store = use_context(HomeContext);
   const params = useParams<{ user_id: string }>();
   const friends = createAsync(async () => {
     return main_store.sqlite.query<Person>(
@@ -61,6 +68,40 @@ export default function FriendList() {
           </dialog>
         </div>
       </div>
+      <For each={home_store.friend_requests()}>
+        {(request) => (
+          <div class="flex border-b border-base-300 p-2 gap-2 items-center">
+            <div class="flex-1 flex flex-col">
+              <span>
+                {request.introduction?.person.name ?? request.remote_id}
+              </span>
+              <span class="text-xs text-base-content/60">
+                {request.introduction?.greeting ?? "请求添加你为好友"}
+              </span>
+            </div>
+            <div class="tooltip" data-tip="接受">
+              <button
+                class="btn btn-square btn-sm btn-ghost"
+                onClick={() =>
+                  home_store.respond_friend_request(request, "Accept")
+                }
+              >
+                <CheckIcon class="size-4" />
+              </button>
+            </div>
+            <div class="tooltip" data-tip="拒绝">
+              <button
+                class="btn btn-square btn-sm btn-ghost"
+                onClick={() =>
+                  home_store.respond_friend_request(request, "Reject")
+                }
+              >
+                <XIcon class="size-4" />
+              </button>
+            </div>
+          </div>
+        )}
+      </For>
       <Suspense>
         <div ref={friend_list_ref} class="flex-1 overflow-y-auto">
           <div
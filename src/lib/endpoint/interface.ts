@@ -1,20 +1,101 @@
 import type { Person } from "~/lib/types";
 import type { Init } from "../interface";
-import type { PersonProtocolEvent } from "./types";
+import type {
+  Avatar,
+  BlobOwner,
+  EventResponse,
+  GroupAction,
+  GroupEvent,
+  GroupMembership,
+  Outcome,
+  PersonProfile,
+  PersonProtocolEvent,
+  Privacy,
+  ProtocolMetrics,
+  RemotePerson,
+  SharedBlob,
+  SharedCollection,
+  StorageUsage,
+  TicketInfo,
+  TicketOptions,
+  TransferEvent,
+} from "./types";
 
 export interface EndpointModule extends Init {
   create_endpoint(secret_key: Uint8Array, person: Person): Promise<Endpoint>;
   generate_secret_key(): Uint8Array | Promise<Uint8Array>;
   get_secret_key_id(secret_key: Uint8Array): string | Promise<string>;
+  parse_ticket(ticket: string): TicketInfo | Promise<TicketInfo>;
 }
 
 export interface Endpoint {
   close(): Promise<void>;
   id(): string | Promise<string>;
-  person_protocol_next_event(): Promise<PersonProtocolEvent>;
-  person_protocol_event<T>(method: string): Promise<T>;
-  request_person(id: string): Promise<Person>;
-  request_friend(id: string): Promise<boolean>;
-  request_chat(id: string): Promise<bigint | null>;
+  on_person_protocol_event(
+    callback: (event: PersonProtocolEvent) => void,
+  ): () => void;
+  respond(event_id: number, response: EventResponse): Promise<bigint | null>;
+  request_person(id: string): Promise<RemotePerson>;
+  request_profile(id: string, version: number): Promise<PersonProfile | null>;
+  profile(): Promise<PersonProfile>;
+  update_person(person: Person): Promise<void>;
+  fetch_avatar(id: string, avatar: Avatar): Promise<Uint8Array>;
+  request_friend(id: string, greeting?: string): Promise<Outcome>;
+  request_chat(id: string): Promise<Outcome<bigint>>;
+  send_message(chat_handle: bigint, payload: Uint8Array): Promise<void>;
+  next_message(chat_handle: bigint): Promise<Uint8Array | null>;
+  close_chat(chat_handle: bigint): Promise<void>;
+  create_group_ticket(
+    group_handle: bigint,
+    options: TicketOptions,
+  ): Promise<string>;
+  create_group(name: string): Promise<bigint>;
   subscribe_group(ticket: string): Promise<bigint>;
+  invite_to_group(friend_id: string, group_handle: bigint): Promise<Outcome>;
+  group_membership(group_handle: bigint): Promise<GroupMembership | null>;
+  manage_group(group_handle: bigint, action: GroupAction): Promise<void>;
+  group_broadcast(group_handle: bigint, payload: Uint8Array): Promise<void>;
+  group_next_event(group_handle: bigint): Promise<GroupEvent | null>;
+  group_neighbors(group_handle: bigint): Promise<string[]>;
+  leave_group(group_handle: bigint): Promise<void>;
+  share_file(path: string): Promise<SharedBlob>;
+  share_bytes(bytes: Uint8Array): Promise<SharedBlob>;
+  download(
+    id: string,
+    hash: string,
+    on_event: (event: TransferEvent) => void,
+    dest?: string,
+  ): Promise<bigint>;
+  share_directory(path: string): Promise<SharedCollection>;
+  list_collection(id: string, hash: string): Promise<SharedCollection>;
+  download_collection(
+    id: string,
+    hash: string,
+    on_event: (event: TransferEvent) => void,
+    entries?: number[],
+    dest?: string,
+  ): Promise<bigint>;
+  cancel_transfer(transfer_handle: bigint): Promise<void>;
+  read_blob(hash: string): Promise<Uint8Array>;
+  tag_blob(hash: string, owner: BlobOwner): Promise<void>;
+  untag_blob(hash: string, owner: BlobOwner): Promise<void>;
+  delete_blob(hash: string): Promise<void>;
+  clear_cache(): Promise<void>;
+  storage_usage(): Promise<StorageUsage>;
+  set_storage_quota(quota: number | null): Promise<void>;
+  block(id: string): Promise<void>;
+  unblock(id: string): Promise<void>;
+  list_blocked(): Promise<string[]>;
+  allow(id: string): Promise<void>;
+  disallow(id: string): Promise<void>;
+  list_allowed(): Promise<string[]>;
+  allow_only(): Promise<boolean>;
+  set_allow_only(allow_only: boolean): Promise<void>;
+  protocol_metrics(): Promise<ProtocolMetrics>;
+  unfriend(id: string): Promise<void>;
+  friend_status(id: string): Promise<boolean>;
+  add_friend(id: string): Promise<void>;
+  list_friends(): Promise<string[]>;
+  privacy(): Promise<Privacy>;
+  set_privacy(privacy: Privacy): Promise<void>;
 }
@@ -1,7 +1,26 @@
 import { createTauRPCProxy, type JsonValue } from "~/generated/ipc_bindings";
 import type { Person } from "../types";
 import type { Endpoint, EndpointModule } from "./interface";
-import type { PersonProtocolEvent } from "./types";
+import type {
+  Avatar,
+  BlobOwner,
+  EventResponse,
+  GroupAction,
+  GroupEvent,
+  GroupMembership,
+  Outcome,
+  PersonProfile,
+  PersonProtocolEvent,
+  Privacy,
+  ProtocolMetrics,
+  RemotePerson,
+  SharedBlob,
+  SharedCollection,
+  StorageUsage,
+  TicketInfo,
+  TicketOptions,
+  TransferEvent,
+} from "./types";
 
 export class EndpointModuleImpl implements EndpointModule {
   init() {}
@@ -18,21 +37,37 @@ export class EndpointModuleImpl implements EndpointModule {
       Array.from(secret_key),
     );
   }
+  async parse_ticket(ticket: string) {
+    return (await createTauRPCProxy().endpoint.parse_ticket(
+      ticket,
+    )) as unknown as TicketInfo;
+  }
 }
 
 export class EndpointImpl implements Endpoint {
   private handle: bigint;
+  private on_person_protocol_events: ((event: PersonProtocolEvent) => void)[];
 
-  private constructor(handle: bigint) {
+  private constructor(
+    handle: bigint,
+    on_person_protocol_events: ((event: PersonProtocolEvent) => void)[],
+  ) {
     this.handle = handle;
+    this.on_person_protocol_events = on_person_protocol_events;
   }
   static async new(secret_key: Uint8Array, person: Person) {
-    return new EndpointImpl(
-      await createTauRPCProxy().endpoint.open_endpoint(
-        Array.from(secret_key),
-        person as unknown as JsonValue,
+    const handle = await createTauRPCProxy().endpoint.open_endpoint(
+      Array.from(secret_key),
+      person as unknown as JsonValue,
+    );
+    const on_person_protocol_events: ((event: PersonProtocolEvent) => void)[] =
+      [];
+    await createTauRPCProxy().endpoint.on_person_protocol_event(handle, (e) =>
+      on_person_protocol_events.forEach((f) =>
+        f(e as unknown as PersonProtocolEvent),
       ),
     );
+    return new EndpointImpl(handle, on_person_protocol_events);
   }
   async close() {
     await createTauRPCProxy().endpoint.close_endpoint(this.handle);
@@ -40,28 +75,96 @@ export class EndpointImpl implements Endpoint {
   async id() {
     return await createTauRPCProxy().endpoint.id(this.handle);
   }
-  async person_protocol_next_event() {
-    return (await createTauRPCProxy().endpoint.person_protocol_next_event(
-      this.handle,
-    )) as PersonProtocolEvent;
+  on_person_protocol_event(callback: (event: PersonProtocolEvent) => void) {
+    this.on_person_protocol_events.push(callback);
+    return () => {
+      const index = this.on_person_protocol_events.indexOf(callback);
+      if (index !== -1) this.on_person_protocol_events.splice(index, 1);
+    };
   }
-  async person_protocol_event<T>(method: string) {
-    return (await createTauRPCProxy().endpoint.person_protocol_event(
+  async respond(event_id: number, response: EventResponse) {
+    return await createTauRPCProxy().endpoint.respond(
       this.handle,
-      method,
-    )) as T;
+      BigInt(event_id),
+      response,
+    );
   }
   async request_person(id: string) {
     return (await createTauRPCProxy().endpoint.request_person(
       this.handle,
       id,
-    )) as unknown as Person;
+    )) as unknown as RemotePerson;
+  }
+  async request_profile(id: string, version: number) {
+    return (await createTauRPCProxy().endpoint.request_profile(
+      this.handle,
+      id,
+      BigInt(version),
+    )) as unknown as PersonProfile | null;
+  }
+  async profile() {
+    return (await createTauRPCProxy().endpoint.profile(
+      this.handle,
+    )) as unknown as PersonProfile;
+  }
+  async update_person(person: Person) {
+    await createTauRPCProxy().endpoint.update_person(
+      this.handle,
+      person as unknown as JsonValue,
+    );
+  }
+  async fetch_avatar(id: string, avatar: Avatar) {
+    return Uint8Array.from(
+      await createTauRPCProxy().endpoint.fetch_avatar(
+        this.handle,
+        id,
+        avatar.hash,
+        BigInt(avatar.size),
+      ),
+    );
+  }
+  async request_friend(id: string, greeting?: string) {
+    return (await createTauRPCProxy().endpoint.request_friend(
+      this.handle,
+      id,
+      greeting ?? null,
+    )) as unknown as Outcome;
+  }
+  async request_chat(id: string): Promise<Outcome<bigint>> {
+    const outcome = (await createTauRPCProxy().endpoint.request_chat(
+      this.handle,
+      id,
+    )) as unknown as Outcome<number>;
+    return outcome.type === "Accepted"
+      ? { type: outcome.type, value: BigInt(outcome.value) }
+      : outcome;
+  }
+  async send_message(chat_handle: bigint, payload: Uint8Array) {
+    await createTauRPCProxy().endpoint.send_message(
+      this.handle,
+      chat_handle,
+      Array.from(payload),
+    );
   }
-  async request_friend(id: string) {
-    return await createTauRPCProxy().endpoint.request_friend(this.handle, id);
+  async next_message(chat_handle: bigint) {
+    const message = await createTauRPCProxy().endpoint.next_message(
+      this.handle,
+      chat_handle,
+    );
+    return message !== null ? Uint8Array.from(message) : null;
   }
-  async request_chat(id: string) {
-    return await createTauRPCProxy().endpoint.request_chat(this.handle, id);
+  async close_chat(chat_handle: bigint) {
+    await createTauRPCProxy().endpoint.close_chat(this.handle, chat_handle);
+  }
+  async create_group_ticket(group_handle: bigint, options: TicketOptions) {
+    return await createTauRPCProxy().endpoint.create_group_ticket(
+      this.handle,
+      group_handle,
+      options as unknown as JsonValue,
+    );
+  }
+  async create_group(name: string) {
+    return await createTauRPCProxy().endpoint.create_group(this.handle, name);
   }
   async subscribe_group(ticket: string) {
     return await createTauRPCProxy().endpoint.subscribe_group(
@@ -69,4 +172,187 @@ export class EndpointImpl implements Endpoint {
       ticket,
     );
   }
+  async invite_to_group(friend_id: string, group_handle: bigint) {
+    return (await createTauRPCProxy().endpoint.invite_to_group(
+      this.handle,
+      friend_id,
+      group_handle,
+    )) as unknown as Outcome;
+  }
+  async group_membership(group_handle: bigint) {
+    return (await createTauRPCProxy().endpoint.group_membership(
+      this.handle,
+      group_handle,
+    )) as unknown as GroupMembership | null;
+  }
+  async manage_group(group_handle: bigint, action: GroupAction) {
+    await createTauRPCProxy().endpoint.manage_group(
+      this.handle,
+      group_handle,
+      action as unknown as JsonValue,
+    );
+  }
+  async group_broadcast(group_handle: bigint, payload: Uint8Array) {
+    await createTauRPCProxy().endpoint.group_broadcast(
+      this.handle,
+      group_handle,
+      Array.from(payload),
+    );
+  }
+  async group_next_event(group_handle: bigint) {
+    return (await createTauRPCProxy().endpoint.group_next_event(
+      this.handle,
+      group_handle,
+    )) as unknown as GroupEvent | null;
+  }
+  async group_neighbors(group_handle: bigint) {
+    return await createTauRPCProxy().endpoint.group_neighbors(
+      this.handle,
+      group_handle,
+    );
+  }
+  async leave_group(group_handle: bigint) {
+    await createTauRPCProxy().endpoint.leave_group(this.handle, group_handle);
+  }
+  async share_file(path: string) {
+    return (await createTauRPCProxy().endpoint.share_file(
+      this.handle,
+      path,
+    )) as unknown as SharedBlob;
+  }
+  async share_bytes(bytes: Uint8Array) {
+    return (await createTauRPCProxy().endpoint.share_bytes(
+      this.handle,
+      Array.from(bytes),
+    )) as unknown as SharedBlob;
+  }
+  async download(
+    id: string,
+    hash: string,
+    on_event: (event: TransferEvent) => void,
+    dest?: string,
+  ) {
+    return await createTauRPCProxy().endpoint.download(
+      this.handle,
+      id,
+      hash,
+      dest ?? null,
+      (e) => on_event(e as unknown as TransferEvent),
+    );
+  }
+  async share_directory(path: string) {
+    return (await createTauRPCProxy().endpoint.share_directory(
+      this.handle,
+      path,
+    )) as unknown as SharedCollection;
+  }
+  async list_collection(id: string, hash: string) {
+    return (await createTauRPCProxy().endpoint.list_collection(
+      this.handle,
+      id,
+      hash,
+    )) as unknown as SharedCollection;
+  }
+  async download_collection(
+    id: string,
+    hash: string,
+    on_event: (event: TransferEvent) => void,
+    entries?: number[],
+    dest?: string,
+  ) {
+    return await createTauRPCProxy().endpoint.download_collection(
+      this.handle,
+      id,
+      hash,
+      entries?.map(BigInt) ?? null,
+      dest ?? null,
+      (e) => on_event(e as unknown as TransferEvent),
+    );
+  }
+  async cancel_transfer(transfer_handle: bigint) {
+    await createTauRPCProxy().endpoint.cancel_transfer(
+      this.handle,
+      transfer_handle,
+    );
+  }
+  async read_blob(hash: string) {
+    return Uint8Array.from(
+      await createTauRPCProxy().endpoint.read_blob(this.handle, hash),
+    );
+  }
+  async tag_blob(hash: string, owner: BlobOwner) {
+    await createTauRPCProxy().endpoint.tag_blob(this.handle, hash, owner);
+  }
+  async untag_blob(hash: string, owner: BlobOwner) {
+    await createTauRPCProxy().endpoint.untag_blob(this.handle, hash, owner);
+  }
+  async delete_blob(hash: string) {
+    await createTauRPCProxy().endpoint.delete_blob(this.handle, hash);
+  }
+  async clear_cache() {
+    await createTauRPCProxy().endpoint.clear_cache(this.handle);
+  }
+  async storage_usage() {
+    return (await createTauRPCProxy().endpoint.storage_usage(
+      this.handle,
+    )) as unknown as StorageUsage;
+  }
+  async set_storage_quota(quota: number | null) {
+    await createTauRPCProxy().endpoint.set_storage_quota(
+      this.handle,
+      quota === null ? null : BigInt(quota),
+    );
+  }
+  async block(id: string) {
+    await createTauRPCProxy().endpoint.block(this.handle, id);
+  }
+  async unblock(id: string) {
+    await createTauRPCProxy().endpoint.unblock(this.handle, id);
+  }
+  async list_blocked() {
+    return await createTauRPCProxy().endpoint.list_blocked(this.handle);
+  }
+  async allow(id: string) {
+    await createTauRPCProxy().endpoint.allow(this.handle, id);
+  }
+  async disallow(id: string) {
+    await createTauRPCProxy().endpoint.disallow(this.handle, id);
+  }
+  async list_allowed() {
+    return await createTauRPCProxy().endpoint.list_allowed(this.handle);
+  }
+  async allow_only() {
+    return await createTauRPCProxy().endpoint.allow_only(this.handle);
+  }
+  async set_allow_only(allow_only: boolean) {
+    await createTauRPCProxy().endpoint.set_allow_only(this.handle, allow_only);
+  }
+  async protocol_metrics() {
+    return (await createTauRPCProxy().endpoint.protocol_metrics(
+      this.handle,
+    )) as unknown as ProtocolMetrics;
+  }
+  async unfriend(id: string) {
+    await createTauRPCProxy().endpoint.unfriend(this.handle, id);
+  }
+  async friend_status(id: string) {
+    return await createTauRPCProxy().endpoint.friend_status(this.handle, id);
+  }
+  async add_friend(id: string) {
+    await createTauRPCProxy().endpoint.add_friend(this.handle, id);
+  }
+  async list_friends() {
+    return await createTauRPCProxy().endpoint.list_friends(this.handle);
+  }
+  async privacy() {
+    return (await createTauRPCProxy().endpoint.privacy(
+      this.handle,
+    )) as unknown as Privacy;
+  }
+  async set_privacy(privacy: Privacy) {
+    await createTauRPCProxy().endpoint.set_privacy(
+      this.handle,
+      privacy as unknown as JsonValue,
+    );
+  }
 }
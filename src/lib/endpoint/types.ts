@@ -1 +1,169 @@
-export type PersonProtocolEvent = "FriendRequest" | "ChatRequest";
+export type PersonProtocolEventKind =
+  | "FriendRequest"
+  | "ChatRequest"
+  | "RequestExpired"
+  | "Unfriended"
+  | "PersonUpdated"
+  | "GroupInvite";
+
+export interface Avatar {
+  hash: string;
+  size: number;
+}
+
+export interface RemotePerson {
+  name: string;
+  avatar: Avatar | null;
+  bio: string;
+}
+
+export interface Introduction {
+  greeting: string | null;
+  person: RemotePerson;
+  sent_at: number;
+}
+
+export interface PersonProfile {
+  person: RemotePerson;
+  version: number;
+}
+
+export interface Invitation {
+  ticket: string;
+  group_name: string;
+}
+
+export interface PersonProtocolEvent {
+  id: number | null;
+  kind: PersonProtocolEventKind;
+  remote_id: string;
+  timestamp: number;
+  introduction: Introduction | null;
+  profile: PersonProfile | null;
+  invitation: Invitation | null;
+}
+
+export type EventResponse = "Accept" | "Reject";
+
+export type Visibility = "Public" | "Friends" | "Nobody";
+
+export interface Privacy {
+  name: Visibility;
+  avatar: Visibility;
+  bio: Visibility;
+}
+
+export interface SharedBlob {
+  hash: string;
+  size: number;
+}
+
+export interface CollectionEntry {
+  name: string;
+  hash: string;
+  size: number | null;
+}
+
+export interface SharedCollection {
+  hash: string;
+  entries: CollectionEntry[];
+}
+
+export type BlobOwner =
+  | "Avatar"
+  | "ChatAttachment"
+  | "GroupHistory"
+  | "Share"
+  | "Download"
+  | "Cache";
+
+export interface StorageUsage {
+  total: number;
+  avatar: number;
+  chat_attachment: number;
+  group_history: number;
+  share: number;
+  download: number;
+  cache: number;
+  untagged: number;
+  quota: number | null;
+}
+
+export type Role = "Member" | "Admin" | "Owner";
+
+export type GroupAction =
+  | { type: "AddMember"; id: string }
+  | { type: "SetRole"; id: string; role: Role }
+  | { type: "Kick"; id: string }
+  | { type: "Ban"; id: string }
+  | { type: "Unban"; id: string }
+  | { type: "RotateTopic" };
+
+export interface GroupMember {
+  id: string;
+  role: Role;
+}
+
+export interface GroupMembership {
+  group: string;
+  topic: string;
+  name: string;
+  owner: string;
+  version: number;
+  epoch: number;
+  members: GroupMember[];
+  banned: string[];
+}
+
+export type GroupEvent =
+  | {
+      type: "Message";
+      content: number[];
+      delivered_from: string;
+      author: string;
+      sequence: number;
+      timestamp: number;
+    }
+  | { type: "NeighborUp"; id: string }
+  | { type: "NeighborDown"; id: string }
+  | { type: "MembershipChanged"; membership: GroupMembership }
+  | { type: "Removed" }
+  | { type: "Lagged" };
+
+export interface TicketOptions {
+  name?: string;
+  bootstrap?: string[];
+  expires_at?: number | null;
+  max_uses?: number | null;
+  invitee?: string | null;
+}
+
+export interface TicketInfo {
+  id: string;
+  name: string;
+  inviter: string | null;
+  invitee: string | null;
+  bootstrap: string[];
+  expires_at: number | null;
+  max_uses: number | null;
+  expired: boolean;
+}
+
+export type TransferEvent =
+  | { type: "Progress"; downloaded: number }
+  | { type: "Retrying"; attempt: number; error: string }
+  | { type: "Done"; size: number }
+  | { type: "Failed"; error: string };
+
+export interface ProtocolMetrics {
+  rate_limited: number;
+  requests_rejected: number;
+  streams_rejected: number;
+  events_dropped: number;
+  events_rejected: number;
+}
+
+export type Outcome<T = null> =
+  | { type: "Accepted"; value: T }
+  | { type: "Rejected" }
+  | { type: "TimedOut" };
@@ -1,12 +1,32 @@
 import wasm_init, {
   generate_secret_key as wasm_generate_secret_key,
   get_secret_key_id as wasm_get_secret_key_id,
+  parse_ticket as wasm_parse_ticket,
   Endpoint as WasmEndpoint,
 } from "@dp2p/endpoint";
 import wasm_url from "@dp2p/endpoint/endpoint_wasm_bg.wasm?url";
 import type { Person } from "~/lib/types";
 import type { Endpoint, EndpointModule } from "./interface";
-import type { PersonProtocolEvent } from "./types";
+import type {
+  Avatar,
+  BlobOwner,
+  EventResponse,
+  GroupAction,
+  GroupEvent,
+  GroupMembership,
+  Outcome,
+  PersonProfile,
+  PersonProtocolEvent,
+  Privacy,
+  ProtocolMetrics,
+  RemotePerson,
+  SharedBlob,
+  SharedCollection,
+  StorageUsage,
+  TicketInfo,
+  TicketOptions,
+  TransferEvent,
+} from "./types";
 
 export class EndpointModuleImpl implements EndpointModule {
   async init() {
@@ -21,16 +41,30 @@ export class EndpointModuleImpl implements EndpointModule {
   get_secret_key_id(secret_key: Uint8Array) {
     return wasm_get_secret_key_id(secret_key);
   }
+  parse_ticket(ticket: string) {
+    return wasm_parse_ticket(ticket) as TicketInfo;
+  }
 }
 
 export class EndpointImpl implements Endpoint {
   private endpoint: WasmEndpoint;
+  private on_person_protocol_events: ((event: PersonProtocolEvent) => void)[];
 
-  private constructor(endpoint: WasmEndpoint) {
+  private constructor(
+    endpoint: WasmEndpoint,
+    on_person_protocol_events: ((event: PersonProtocolEvent) => void)[],
+  ) {
     this.endpoint = endpoint;
+    this.on_person_protocol_events = on_person_protocol_events;
   }
   static async new(secret_key: Uint8Array, person: Person) {
-    return new EndpointImpl(await WasmEndpoint.new(secret_key, person));
+    const endpoint = await WasmEndpoint.new(secret_key, person);
+    const on_person_protocol_events: ((event: PersonProtocolEvent) => void)[] =
+      [];
+    endpoint.on_person_protocol_event((e: PersonProtocolEvent) =>
+      on_person_protocol_events.forEach((f) => f(e)),
+    );
+    return new EndpointImpl(endpoint, on_person_protocol_events);
   }
   async close() {
     await this.endpoint.close();
@@ -38,23 +72,200 @@ export class EndpointImpl implements Endpoint {
   id() {
     return this.endpoint.id();
   }
-  async person_protocol_next_event() {
-    return (await this.endpoint.person_protocol_next_event()) as PersonProtocolEvent;
+  on_person_protocol_event(callback: (event: PersonProtocolEvent) => void) {
+    this.on_person_protocol_events.push(callback);
+    return () => {
+      const index = this.on_person_protocol_events.indexOf(callback);
+      if (index !== -1) this.on_person_protocol_events.splice(index, 1);
+    };
   }
-  async person_protocol_event<T>(method: string) {
-    return (await this.endpoint.person_protocol_event(method)) as T;
+  async respond(event_id: number, response: EventResponse) {
+    const handle = await this.endpoint.respond(event_id, response);
+    return handle != undefined ? BigInt(handle) : null;
   }
   async request_person(id: string) {
-    return (await this.endpoint.request_person(id)) as Person;
+    return (await this.endpoint.request_person(id)) as RemotePerson;
+  }
+  async request_profile(id: string, version: number) {
+    return (await this.endpoint.request_profile(
+      id,
+      BigInt(version),
+    )) as PersonProfile | null;
+  }
+  async profile() {
+    return this.endpoint.profile() as PersonProfile;
+  }
+  async update_person(person: Person) {
+    await this.endpoint.update_person(person);
+  }
+  async fetch_avatar(id: string, avatar: Avatar) {
+    return await this.endpoint.fetch_avatar(
+      id,
+      avatar.hash,
+      BigInt(avatar.size),
+    );
+  }
+  async request_friend(id: string, greeting?: string) {
+    return (await this.endpoint.request_friend(id, greeting)) as Outcome;
   }
-  async request_friend(id: string) {
-    return await this.endpoint.request_friend(id);
+  async request_chat(id: string): Promise<Outcome<bigint>> {
+    const outcome = (await this.endpoint.request_chat(id)) as Outcome<number>;
+    return outcome.type === "Accepted"
+      ? { type: outcome.type, value: BigInt(outcome.value) }
+      : outcome;
   }
-  async request_chat(id: string) {
-    const a = await this.endpoint.request_chat(id);
-    return a != undefined ? (a as unknown as bigint) : null;
+  async send_message(chat_handle: bigint, payload: Uint8Array) {
+    await this.endpoint.send_message(Number(chat_handle), payload);
+  }
+  async next_message(chat_handle: bigint) {
+    return (await this.endpoint.next_message(Number(chat_handle))) ?? null;
+  }
+  async close_chat(chat_handle: bigint) {
+    await this.endpoint.close_chat(Number(chat_handle));
+  }
+  async create_group_ticket(group_handle: bigint, options: TicketOptions) {
+    return this.endpoint.create_group_ticket(Number(group_handle), options);
+  }
+  async create_group(name: string) {
+    return BigInt(await this.endpoint.create_group(name));
   }
   async subscribe_group(ticket: string) {
-    return (await this.endpoint.subscribe_group(ticket)) as unknown as bigint;
+    return BigInt(await this.endpoint.subscribe_group(ticket));
+  }
+  async invite_to_group(friend_id: string, group_handle: bigint) {
+    return (await this.endpoint.invite_to_group(
+      friend_id,
+      Number(group_handle),
+    )) as Outcome;
+  }
+  async group_membership(group_handle: bigint) {
+    return this.endpoint.group_membership(
+      Number(group_handle),
+    ) as GroupMembership | null;
+  }
+  async manage_group(group_handle: bigint, action: GroupAction) {
+    await this.endpoint.manage_group(Number(group_handle), action);
+  }
+  async group_broadcast(group_handle: bigint, payload: Uint8Array) {
+    await this.endpoint.group_broadcast(Number(group_handle), payload);
+  }
+  async group_next_event(group_handle: bigint) {
+    return ((await this.endpoint.group_next_event(Number(group_handle))) ??
+      null) as GroupEvent | null;
+  }
+  async group_neighbors(group_handle: bigint) {
+    return this.endpoint.group_neighbors(Number(group_handle));
+  }
+  async leave_group(group_handle: bigint) {
+    this.endpoint.leave_group(Number(group_handle));
+  }
+  async share_file(): Promise<SharedBlob> {
+    throw new Error("当前平台不支持文件存储");
+  }
+  async share_bytes(bytes: Uint8Array) {
+    return (await this.endpoint.share_bytes(bytes)) as SharedBlob;
+  }
+  async download(
+    id: string,
+    hash: string,
+    on_event: (event: TransferEvent) => void,
+    dest?: string,
+  ) {
+    if (dest !== undefined) throw new Error("当前平台不支持文件存储");
+    return BigInt(this.endpoint.download(id, hash, on_event));
+  }
+  async share_directory(): Promise<SharedCollection> {
+    throw new Error("当前平台不支持文件存储");
+  }
+  async list_collection(id: string, hash: string) {
+    return (await this.endpoint.list_collection(id, hash)) as SharedCollection;
+  }
+  async download_collection(
+    id: string,
+    hash: string,
+    on_event: (event: TransferEvent) => void,
+    entries?: number[],
+    dest?: string,
+  ) {
+    if (dest !== undefined) throw new Error("当前平台不支持文件存储");
+    return BigInt(
+      await this.endpoint.download_collection(
+        id,
+        hash,
+        entries ? Uint32Array.from(entries) : undefined,
+        on_event,
+      ),
+    );
+  }
+  async cancel_transfer(transfer_handle: bigint) {
+    this.endpoint.cancel_transfer(Number(transfer_handle));
+  }
+  async read_blob(hash: string) {
+    return await this.endpoint.read_blob(hash);
+  }
+  async tag_blob(hash: string, owner: BlobOwner) {
+    await this.endpoint.tag_blob(hash, owner);
+  }
+  async untag_blob(hash: string, owner: BlobOwner) {
+    await this.endpoint.untag_blob(hash, owner);
+  }
+  async delete_blob(hash: string) {
+    await this.endpoint.delete_blob(hash);
+  }
+  async clear_cache() {
+    await this.endpoint.clear_cache();
+  }
+  async storage_usage() {
+    return (await this.endpoint.storage_usage()) as StorageUsage;
+  }
+  async set_storage_quota(quota: number | null) {
+    await this.endpoint.set_storage_quota(
+      quota === null ? undefined : BigInt(quota),
+    );
+  }
+  async block(id: string) {
+    await this.endpoint.block(id);
+  }
+  async unblock(id: string) {
+    await this.endpoint.unblock(id);
+  }
+  async list_blocked() {
+    return this.endpoint.list_blocked();
+  }
+  async allow(id: string) {
+    await this.endpoint.allow(id);
+  }
+  async disallow(id: string) {
+    await this.endpoint.disallow(id);
+  }
+  async list_allowed() {
+    return this.endpoint.list_allowed();
+  }
+  async allow_only() {
+    return this.endpoint.allow_only();
+  }
+  async set_allow_only(allow_only: boolean) {
+    await this.endpoint.set_allow_only(allow_only);
+  }
+  async protocol_metrics() {
+    return this.endpoint.protocol_metrics() as ProtocolMetrics;
+  }
+  async unfriend(id: string) {
+    await this.endpoint.unfriend(id);
+  }
+  async friend_status(id: string) {
+    return await this.endpoint.friend_status(id);
+  }
+  async add_friend(id: string) {
+    await this.endpoint.add_friend(id);
+  }
+  async list_friends() {
+    return this.endpoint.list_friends();
+  }
+  async privacy() {
+    return this.endpoint.privacy() as Privacy;
+  }
+  async set_privacy(privacy: Privacy) {
+    await this.endpoint.set_privacy(privacy);
   }
 }
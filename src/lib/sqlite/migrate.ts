@@ -0,0 +1,31 @@
+import { sql } from "kysely";
+import { QueryBuilder } from "../query_builder";
+import type { SQLite } from "./interface";
+
+const FRIEND_VERSION_MIGRATION = `
+BEGIN;
+CREATE TABLE "friend_migration" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "name" TEXT NOT NULL,
+    "avatar" BLOB,
+    "bio" TEXT NOT NULL,
+    "version" BIGINT NOT NULL DEFAULT 0,
+
+    PRIMARY KEY ("user_id", "id")
+);
+INSERT OR IGNORE INTO "friend_migration" ("id", "user_id", "name", "avatar", "bio")
+SELECT "id", "user_id", "name", "avatar", "bio" FROM "friend";
+DROP TABLE "friend";
+ALTER TABLE "friend_migration" RENAME TO "friend";
+COMMIT;
+`;
+
+export async function migrate(sqlite: SQLite) {
+  const friend_columns = await sqlite.query<{ name: string }>(
+    sql`SELECT name FROM pragma_table_info('friend')`.compile(QueryBuilder),
+  );
+  if (!friend_columns.some((column) => column.name === "version")) {
+    await sqlite.execute_sql(FRIEND_VERSION_MIGRATION);
+  }
+}
@@ -1,14 +1,38 @@
 import type { Endpoint } from "~/lib/endpoint/interface";
+import type {
+  Avatar,
+  EventResponse,
+  PersonProfile,
+  PersonProtocolEvent,
+} from "~/lib/endpoint/types";
+import type { SQLite } from "~/lib/sqlite/interface";
 import type { MainStore } from "./main";
 import type { Store } from "./interface";
 import { QueryBuilder } from "~/lib/query_builder";
 import type { Person } from "~/lib/types";
+import { tryit } from "radash";
+import { createSignal, type Accessor, type Setter } from "solid-js";
 
 export class HomeStore implements Store {
   endpoint: Endpoint;
+  private sqlite: SQLite;
+  private user_id: string;
+  private unsubscribe: () => void;
+  friend_requests: Accessor<PersonProtocolEvent[]>;
+  private set_friend_requests: Setter<PersonProtocolEvent[]>;
 
-  private constructor(endpoint: Endpoint) {
+  private constructor(sqlite: SQLite, user_id: string, endpoint: Endpoint) {
+    this.sqlite = sqlite;
+    this.user_id = user_id;
     this.endpoint = endpoint;
+    const [friend_requests, set_friend_requests] = createSignal<
+      PersonProtocolEvent[]
+    >([]);
+    this.friend_requests = friend_requests;
+    this.set_friend_requests = set_friend_requests;
+    this.unsubscribe = endpoint.on_person_protocol_event((event) =>
+      this.on_person_protocol_event(event),
+    );
   }
   static async new(main_store: MainStore, user_id: string) {
     const user = (
@@ -21,15 +45,128 @@ export class HomeStore implements Store {
       )
     ).at(0);
     if (!user) throw new Error("没有找到相关用户信息");
-    return new HomeStore(
+    const home_store = new HomeStore(
+      main_store.sqlite,
+      user_id,
       await main_store.endpoint_module.create_endpoint(user.key, {
         name: user.name,
         avatar: user.avatar,
         bio: user.bio,
       }),
     );
+    await home_store.sync_friends();
+    return home_store;
+  }
+  async sync_friends() {
+    let friends = await this.endpoint.list_friends();
+    if (friends.length === 0) {
+      const rows = await this.sqlite.query<{ id: string }>(
+        QueryBuilder.selectFrom("friend")
+          .select("id")
+          .where("user_id", "=", this.user_id)
+          .compile(),
+      );
+      for (const row of rows) await this.endpoint.add_friend(row.id);
+      friends = await this.endpoint.list_friends();
+    }
+    await this.sqlite.execute(
+      QueryBuilder.deleteFrom("friend")
+        .where("user_id", "=", this.user_id)
+        .$if(friends.length > 0, (query) =>
+          query.where("id", "not in", friends),
+        )
+        .compile(),
+    );
+    const versions = new Map(
+      (
+        await this.sqlite.query<{ id: string; version: number }>(
+          QueryBuilder.selectFrom("friend")
+            .select(["id", "version"])
+            .where("user_id", "=", this.user_id)
+            .compile(),
+        )
+      ).map((row) => [row.id, Number(row.version)]),
+    );
+    await Promise.all(
+      friends.map((id) => this.refresh_friend(id, versions.get(id) ?? 0)),
+    );
+  }
+  async refresh_friend(id: string, version = 0) {
+    const [err, profile] = await tryit((id: string, version: number) =>
+      this.endpoint.request_profile(id, version),
+    )(id, version);
+    if (err) {
+      console.warn(`获取好友资料失败：${err.message}`);
+    } else if (profile) {
+      await this.save_friend(id, profile);
+    }
+  }
+  private async save_friend(id: string, profile: PersonProfile) {
+    let avatar: Uint8Array | null = null;
+    if (profile.person.avatar) {
+      const [err, bytes] = await tryit((id: string, avatar: Avatar) =>
+        this.endpoint.fetch_avatar(id, avatar),
+      )(id, profile.person.avatar);
+      if (err) console.warn(`获取好友头像失败：${err.message}`);
+      else avatar = bytes;
+    }
+    const values = {
+      name: profile.person.name,
+      avatar,
+      bio: profile.person.bio,
+      version: profile.version,
+    };
+    await this.sqlite.execute(
+      QueryBuilder.insertInto("friend")
+        .values({ id, user_id: this.user_id, ...values })
+        .onConflict((conflict) =>
+          conflict.columns(["user_id", "id"]).doUpdateSet(values),
+        )
+        .compile(),
+    );
+  }
+  async respond_friend_request(
+    event: PersonProtocolEvent,
+    response: EventResponse,
+  ) {
+    this.set_friend_requests((requests) => requests.filter((v) => v !== event));
+    if (event.id === null) return;
+    const [err] = await tryit((id: number, response: EventResponse) =>
+      this.endpoint.respond(id, response),
+    )(event.id, response);
+    if (err) {
+      console.warn(`回应好友请求失败：${err.message}`);
+    } else if (response === "Accept") {
+      await this.refresh_friend(event.remote_id);
+    }
+  }
+  private async on_person_protocol_event(event: PersonProtocolEvent) {
+    switch (event.kind) {
+      case "FriendRequest":
+        this.set_friend_requests((requests) => [...requests, event]);
+        break;
+      case "RequestExpired":
+        this.set_friend_requests((requests) =>
+          requests.filter((v) => v.id !== event.id),
+        );
+        break;
+      case "PersonUpdated":
+        if (event.profile) {
+          await this.save_friend(event.remote_id, event.profile);
+        }
+        break;
+      case "Unfriended":
+        await this.sqlite.execute(
+          QueryBuilder.deleteFrom("friend")
+            .where("user_id", "=", this.user_id)
+            .where("id", "=", event.remote_id)
+            .compile(),
+        );
+        break;
+    }
   }
   async cleanup() {
+    this.unsubscribe();
     await this.endpoint.close();
   }
 }
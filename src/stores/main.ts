@@ -2,6 +2,7 @@ import { EndpointModuleAdapter } from "~/lib/endpoint";
 import type { EndpointModule } from "~/lib/endpoint/interface";
 import { SQLiteModuleAdapter } from "~/lib/sqlite";
 import type { SQLite, SQLiteModule } from "~/lib/sqlite/interface";
+import { migrate } from "~/lib/sqlite/migrate";
 import type { Store } from "./interface";
 
 export class MainStore implements Store {
@@ -23,6 +24,7 @@ export class MainStore implements Store {
     await sqlite_module.init();
     const sqlite = await sqlite_module.create_sqlite("data.db");
     await sqlite.execute_sql(await (await fetch("/db_schema.sql")).text());
+    await migrate(sqlite);
     const endpoint_module = new EndpointModuleAdapter();
     await endpoint_module.init();
     return new MainStore(sqlite_module, endpoint_module, sqlite);
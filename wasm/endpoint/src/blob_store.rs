@@ -0,0 +1,454 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use endpoint::StoreOption;
+use eyre::{Result, eyre};
+use futures::{
+    StreamExt,
+    future::{AbortHandle, Abortable},
+    lock::Mutex,
+};
+use iroh_blobs::{
+    BlobFormat, Hash, HashAndFormat,
+    api::{
+        Store,
+        proto::{Bitfield, BlobStatus},
+    },
+    hashseq::HashSeq,
+};
+use js_sys::{AsyncIterator, IteratorNext, Uint8Array};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue, closure::Closure};
+use wasm_bindgen_futures::{JsFuture, spawn_local};
+use web_sys::{
+    Blob, Event, FileSystemDirectoryHandle, FileSystemFileHandle, FileSystemGetDirectoryOptions,
+    FileSystemGetFileOptions, FileSystemWritableFileStream, VisibilityState,
+};
+
+const MANIFEST: &str = "tags.json";
+const PARTIAL_SUFFIX: &str = ".bao";
+const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+const RESTORE_MAX_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize, Default)]
+struct Manifest {
+    tags: Vec<(String, HashAndFormat)>,
+    partial: Vec<(Hash, Bitfield)>,
+}
+
+#[derive(Clone)]
+struct Tagged {
+    content: HashAndFormat,
+    hashes: Vec<Hash>,
+    resolved: bool,
+}
+
+pub struct OpfsBlobStore {
+    dir: FileSystemDirectoryHandle,
+    store: Store,
+    persisted: RefCell<HashSet<String>>,
+    tags: RefCell<HashMap<String, Tagged>>,
+    dormant: RefCell<HashMap<String, Tagged>>,
+    dormant_partial: RefCell<HashMap<Hash, Bitfield>>,
+    partial: RefCell<HashMap<Hash, Bitfield>>,
+    lock: Mutex<()>,
+}
+impl OpfsBlobStore {
+    pub async fn open(name: &str) -> Result<Self> {
+        let root: FileSystemDirectoryHandle = promise(
+            web_sys::window()
+                .ok_or_else(|| eyre!("无法获取window"))?
+                .navigator()
+                .storage()
+                .get_directory(),
+        )
+        .await?
+        .unchecked_into();
+        let options = FileSystemGetDirectoryOptions::new();
+        options.set_create(true);
+        let dir = promise(root.get_directory_handle_with_options(name, &options))
+            .await?
+            .unchecked_into();
+        let blob_store = Self {
+            dir,
+            store: StoreOption::Memory.load().await?,
+            persisted: Default::default(),
+            tags: Default::default(),
+            dormant: Default::default(),
+            dormant_partial: Default::default(),
+            partial: Default::default(),
+            lock: Mutex::new(()),
+        };
+        blob_store.restore().await?;
+        Ok(blob_store)
+    }
+    pub fn store(&self) -> Store {
+        self.store.clone()
+    }
+    pub async fn sync(&self) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut current = HashMap::new();
+        for tag in self.store.tags().list().await?.collect::<Vec<_>>().await {
+            let tag = tag?;
+            if let Ok(name) = String::from_utf8(tag.name.0.to_vec()) {
+                current.insert(name, tag.hash_and_format());
+            }
+        }
+        let unchanged = self.partial.borrow().is_empty() && {
+            let tags = self.tags.borrow();
+            tags.len() == current.len()
+                && current.iter().all(|(name, content)| {
+                    tags.get(name)
+                        .is_some_and(|v| v.resolved && v.content == *content)
+                })
+        };
+        if unchanged {
+            return Ok(());
+        }
+        let mut tags = HashMap::new();
+        for (name, content) in current {
+            let known = self
+                .tags
+                .borrow()
+                .get(&name)
+                .filter(|v| v.resolved && v.content == content)
+                .cloned();
+            let tagged = match known {
+                Some(tagged) => tagged,
+                None => self.resolve(content).await,
+            };
+            tags.insert(name, tagged);
+        }
+        self.dormant
+            .borrow_mut()
+            .retain(|name, _| !tags.contains_key(name));
+        let dormant_hashes = self
+            .dormant
+            .borrow()
+            .values()
+            .flat_map(|v| v.hashes.iter().copied())
+            .collect::<HashSet<_>>();
+        self.dormant_partial
+            .borrow_mut()
+            .retain(|hash, _| dormant_hashes.contains(hash));
+        let hashes = tags
+            .values()
+            .flat_map(|v| v.hashes.iter().copied())
+            .collect::<HashSet<_>>();
+        let mut live = HashSet::new();
+        let mut partial = HashMap::new();
+        for hash in hashes {
+            let name = hash.to_hex();
+            if self.persisted.borrow().contains(&name) {
+                live.insert(name);
+                continue;
+            }
+            match self.store.status(hash).await? {
+                BlobStatus::Complete { .. } => {
+                    self.write(&name, &self.store.get_bytes(hash).await?)
+                        .await?;
+                    live.insert(name);
+                }
+                BlobStatus::Partial { .. } => {
+                    let bitfield = self.store.observe(hash).await?;
+                    let name = partial_name(hash);
+                    let changed = self.partial.borrow().get(&hash) != Some(&bitfield);
+                    if changed || !self.persisted.borrow().contains(&name) {
+                        let bao = self
+                            .store
+                            .export_bao(hash, bitfield.ranges.clone())
+                            .bao_to_vec()
+                            .await?;
+                        self.write(&name, &bao).await?;
+                    }
+                    live.insert(name);
+                    partial.insert(hash, bitfield);
+                }
+                BlobStatus::NotFound => {}
+            }
+        }
+        for hash in dormant_hashes {
+            live.insert(hash.to_hex());
+            live.insert(partial_name(hash));
+        }
+        let stale = self
+            .persisted
+            .borrow()
+            .iter()
+            .filter(|v| *v != MANIFEST && !live.contains(*v))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in stale {
+            promise(self.dir.remove_entry(&name)).await?;
+            self.persisted.borrow_mut().remove(&name);
+        }
+        let manifest = Manifest {
+            tags: tags
+                .iter()
+                .chain(self.dormant.borrow().iter())
+                .map(|(name, tagged)| (name.clone(), tagged.content))
+                .collect(),
+            partial: partial
+                .iter()
+                .chain(
+                    self.dormant_partial
+                        .borrow()
+                        .iter()
+                        .filter(|(k, _)| !partial.contains_key(*k)),
+                )
+                .map(|(k, v)| (*k, v.clone()))
+                .collect(),
+        };
+        *self.tags.borrow_mut() = tags;
+        *self.partial.borrow_mut() = partial;
+        self.write(MANIFEST, &serde_json::to_vec(&manifest)?).await
+    }
+    async fn resolve(&self, content: HashAndFormat) -> Tagged {
+        let mut tagged = Tagged {
+            content,
+            hashes: vec![content.hash],
+            resolved: content.format == BlobFormat::Raw,
+        };
+        if content.format == BlobFormat::HashSeq
+            && let Ok(bytes) = self.store.get_bytes(content.hash).await
+            && let Ok(hash_seq) = HashSeq::try_from(bytes)
+        {
+            tagged.hashes.extend(hash_seq);
+            tagged.resolved = true;
+        }
+        tagged
+    }
+    async fn restore(&self) -> Result<()> {
+        let names = self.names().await?;
+        *self.persisted.borrow_mut() = names.clone();
+        let manifest: Manifest = if names.contains(MANIFEST) {
+            serde_json::from_slice(&self.read(MANIFEST).await?)?
+        } else {
+            Default::default()
+        };
+        let bitfields = manifest.partial.into_iter().collect::<HashMap<_, _>>();
+        let mut restored = HashSet::new();
+        let mut restored_size = 0;
+        let mut temp_tags = Vec::new();
+        let mut partial = HashMap::new();
+        let mut tags = HashMap::new();
+        let mut dormant = HashMap::new();
+        for (name, content) in manifest.tags {
+            let tagged = self.resolve_persisted(content, &names).await;
+            let mut size = 0;
+            for hash in tagged.hashes.iter().filter(|v| !restored.contains(*v)) {
+                for file in [hash.to_hex(), partial_name(*hash)] {
+                    if names.contains(&file) {
+                        size += self.size(&file).await?;
+                    }
+                }
+            }
+            if restored_size + size > RESTORE_MAX_SIZE {
+                log::warn!("持久化数据超出恢复上限，暂不加载{}", name);
+                dormant.insert(name, tagged);
+                continue;
+            }
+            restored_size += size;
+            for hash in &tagged.hashes {
+                if !restored.insert(*hash) {
+                    continue;
+                }
+                let file = hash.to_hex();
+                if names.contains(&file) {
+                    let temp_tag = self
+                        .store
+                        .add_bytes(self.read(&file).await?)
+                        .temp_tag()
+                        .await?;
+                    if temp_tag.hash() != *hash {
+                        log::warn!("持久化数据{}已损坏", file);
+                        continue;
+                    }
+                    temp_tags.push(temp_tag);
+                    continue;
+                }
+                let file = partial_name(*hash);
+                let Some(bitfield) = bitfields.get(hash).filter(|_| names.contains(&file)) else {
+                    continue;
+                };
+                if let Err(err) = self
+                    .store
+                    .import_bao_bytes(*hash, bitfield.ranges.clone(), self.read(&file).await?)
+                    .await
+                {
+                    log::warn!("持久化数据{}已损坏：{}", file, err);
+                    continue;
+                }
+                partial.insert(*hash, bitfield.clone());
+            }
+            if !matches!(self.store.status(content.hash).await?, BlobStatus::NotFound) {
+                self.store.tags().set(name.as_str(), content).await?;
+                tags.insert(name, tagged);
+            }
+        }
+        *self.dormant_partial.borrow_mut() = dormant
+            .values()
+            .flat_map(|v: &Tagged| v.hashes.iter())
+            .filter_map(|hash| Some((*hash, bitfields.get(hash)?.clone())))
+            .collect();
+        *self.partial.borrow_mut() = partial;
+        *self.tags.borrow_mut() = tags;
+        *self.dormant.borrow_mut() = dormant;
+        Ok(())
+    }
+    async fn resolve_persisted(&self, content: HashAndFormat, names: &HashSet<String>) -> Tagged {
+        let mut tagged = Tagged {
+            content,
+            hashes: vec![content.hash],
+            resolved: content.format == BlobFormat::Raw,
+        };
+        if content.format == BlobFormat::HashSeq
+            && names.contains(&content.hash.to_hex())
+            && let Ok(bytes) = self.read(&content.hash.to_hex()).await
+            && let Ok(hash_seq) = HashSeq::try_from(Bytes::from(bytes))
+        {
+            tagged.hashes.extend(hash_seq);
+            tagged.resolved = true;
+        }
+        tagged
+    }
+    async fn names(&self) -> Result<HashSet<String>> {
+        let keys: AsyncIterator = self.dir.keys();
+        let mut names = HashSet::new();
+        loop {
+            let next: IteratorNext = promise(keys.next().map_err(js_error)?)
+                .await?
+                .unchecked_into();
+            if next.done() {
+                break;
+            }
+            if let Some(name) = next.value().as_string() {
+                names.insert(name);
+            }
+        }
+        Ok(names)
+    }
+    async fn file(&self, name: &str) -> Result<Blob> {
+        let file: FileSystemFileHandle = promise(self.dir.get_file_handle(name))
+            .await?
+            .unchecked_into();
+        Ok(promise(file.get_file()).await?.unchecked_into())
+    }
+    async fn size(&self, name: &str) -> Result<u64> {
+        Ok(self.file(name).await?.size() as u64)
+    }
+    async fn read(&self, name: &str) -> Result<Vec<u8>> {
+        let blob = self.file(name).await?;
+        Ok(Uint8Array::new(&promise(blob.array_buffer()).await?).to_vec())
+    }
+    async fn write(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        let options = FileSystemGetFileOptions::new();
+        options.set_create(true);
+        let file: FileSystemFileHandle =
+            promise(self.dir.get_file_handle_with_options(name, &options))
+                .await?
+                .unchecked_into();
+        let writable: FileSystemWritableFileStream =
+            promise(file.create_writable()).await?.unchecked_into();
+        promise(writable.write_with_u8_array(bytes).map_err(js_error)?).await?;
+        promise(writable.close()).await?;
+        self.persisted.borrow_mut().insert(name.to_string());
+        Ok(())
+    }
+}
+
+pub struct BlobSync {
+    blob_store: Rc<OpfsBlobStore>,
+    abort_handle: AbortHandle,
+    on_hide: Closure<dyn Fn(Event)>,
+}
+impl BlobSync {
+    pub fn spawn(blob_store: OpfsBlobStore) -> Self {
+        let blob_store = Rc::new(blob_store);
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        let task = {
+            let blob_store = blob_store.clone();
+            async move {
+                loop {
+                    n0_future::time::sleep(SYNC_INTERVAL).await;
+                    if let Err(err) = blob_store.sync().await {
+                        log::error!("{}", err);
+                    }
+                }
+            }
+        };
+        spawn_local(async move {
+            Abortable::new(task, abort_registration).await.ok();
+        });
+        let on_hide = Closure::<dyn Fn(Event)>::new({
+            let blob_store = blob_store.clone();
+            move |event: Event| {
+                let hidden = web_sys::window()
+                    .and_then(|v| v.document())
+                    .is_some_and(|v| v.visibility_state() == VisibilityState::Hidden);
+                if event.type_() != "pagehide" && !hidden {
+                    return;
+                }
+                let blob_store = blob_store.clone();
+                spawn_local(async move {
+                    if let Err(err) = blob_store.sync().await {
+                        log::error!("{}", err);
+                    }
+                });
+            }
+        });
+        if let Some(window) = web_sys::window() {
+            let callback = on_hide.as_ref().unchecked_ref();
+            if let Err(err) = window.add_event_listener_with_callback("pagehide", callback) {
+                log::warn!("监听页面隐藏事件失败：{:?}", err);
+            }
+            if let Some(document) = window.document()
+                && let Err(err) =
+                    document.add_event_listener_with_callback("visibilitychange", callback)
+            {
+                log::warn!("监听页面隐藏事件失败：{:?}", err);
+            }
+        }
+        Self {
+            blob_store,
+            abort_handle,
+            on_hide,
+        }
+    }
+    pub async fn flush(&self) -> Result<()> {
+        self.blob_store.sync().await
+    }
+}
+impl Drop for BlobSync {
+    fn drop(&mut self) {
+        self.abort_handle.abort();
+        if let Some(window) = web_sys::window() {
+            let callback = self.on_hide.as_ref().unchecked_ref();
+            window
+                .remove_event_listener_with_callback("pagehide", callback)
+                .ok();
+            if let Some(document) = window.document() {
+                document
+                    .remove_event_listener_with_callback("visibilitychange", callback)
+                    .ok();
+            }
+        }
+    }
+}
+
+fn partial_name(hash: Hash) -> String {
+    format!("{}{}", hash.to_hex(), PARTIAL_SUFFIX)
+}
+
+async fn promise(promise: js_sys::Promise) -> Result<JsValue> {
+    JsFuture::from(promise).await.map_err(js_error)
+}
+
+fn js_error(err: JsValue) -> eyre::Report {
+    eyre!("{:?}", err)
+}
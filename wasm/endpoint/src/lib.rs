@@ -1,9 +1,20 @@
+mod blob_store;
 mod error;
+mod policy_store;
 
-use eyre::Result;
+use std::{cell::RefCell, rc::Rc};
+
+use eyre::{Result, eyre};
+use futures::future::{AbortHandle, Abortable};
+use js_sys::Function;
 use wasm_bindgen::{JsError, JsValue, prelude::wasm_bindgen};
+use wasm_bindgen_futures::spawn_local;
 
-use crate::error::MapJsError;
+use crate::{
+    blob_store::{BlobSync, OpfsBlobStore},
+    error::MapJsError,
+    policy_store::LocalStoragePolicyStore,
+};
 
 #[wasm_bindgen(start)]
 fn start() {
@@ -12,45 +23,361 @@ fn start() {
 }
 
 #[wasm_bindgen]
-pub struct Endpoint(endpoint::Endpoint);
+pub struct Endpoint(
+    endpoint::Endpoint,
+    Option<Rc<BlobSync>>,
+    RefCell<Option<AbortHandle>>,
+);
 #[wasm_bindgen]
 impl Endpoint {
     pub async fn new(secret_key: Vec<u8>, person: JsValue) -> Result<Self, JsError> {
+        let secret_key_id = endpoint::get_secret_key_id(secret_key.clone()).mje()?;
+        let mut builder =
+            endpoint::Endpoint::builder(secret_key, serde_wasm_bindgen::from_value(person)?)
+                .policy_store(LocalStoragePolicyStore::new(format!(
+                    "policy-{}",
+                    secret_key_id
+                )));
+        let blob_store = match OpfsBlobStore::open(&format!("store-{}", secret_key_id)).await {
+            Ok(blob_store) => {
+                builder = builder.blob_store(blob_store.store());
+                Some(blob_store)
+            }
+            Err(err) => {
+                log::warn!("浏览器持久化存储不可用，改用内存存储：{}", err);
+                None
+            }
+        };
         Ok(Self(
-            endpoint::Endpoint::new(secret_key, serde_wasm_bindgen::from_value(person)?)
-                .await
-                .mje()?,
+            builder.bind().await.mje()?,
+            blob_store.map(|blob_store| Rc::new(BlobSync::spawn(blob_store))),
+            Default::default(),
         ))
     }
     pub async fn close(self) -> Result<(), JsError> {
+        if let Some(forwarder) = self.2.take() {
+            forwarder.abort();
+        }
+        self.flush().await?;
         self.0.close().await.mje()?;
         Ok(())
     }
     pub fn id(&self) -> String {
         self.0.id()
     }
-    pub async fn person_protocol_next_event(&self) -> Result<String, JsError> {
-        self.0.person_protocol_next_event().await.mje()
+    pub fn on_person_protocol_event(&self, callback: Function) {
+        let endpoint = self.0.clone();
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        if let Some(forwarder) = self.2.replace(Some(abort_handle)) {
+            forwarder.abort();
+        }
+        let forwarder = async move {
+            loop {
+                if let Err(err) = async {
+                    callback
+                        .call1(
+                            &JsValue::NULL,
+                            &serde_wasm_bindgen::to_value(&endpoint.next_event().await?)
+                                .map_err(|err| eyre!(err.to_string()))?,
+                        )
+                        .map_err(|err| eyre!("{:?}", err))?;
+                    eyre::Ok(())
+                }
+                .await
+                {
+                    log::error!("{}", err);
+                    break;
+                }
+            }
+        };
+        spawn_local(async move {
+            Abortable::new(forwarder, abort_registration).await.ok();
+        });
+    }
+    pub async fn respond(
+        &self,
+        event_id: usize,
+        response: JsValue,
+    ) -> Result<Option<usize>, JsError> {
+        self.0
+            .respond(event_id, serde_wasm_bindgen::from_value(response)?)
+            .await
+            .mje()
     }
-    pub fn person_protocol_event(&self, method: String) -> Result<JsValue, JsError> {
+    pub async fn request_person(&self, id: String) -> Result<JsValue, JsError> {
+        let person = self.0.request_person(id).await.mje()?;
+        self.flush().await?;
+        Ok(serde_wasm_bindgen::to_value(&person)?)
+    }
+    pub async fn request_profile(&self, id: String, version: u64) -> Result<JsValue, JsError> {
         Ok(serde_wasm_bindgen::to_value(
-            &self.0.person_protocol_event(method).mje()?,
+            &self.0.request_profile(id, version).await.mje()?,
         )?)
     }
-    pub async fn request_person(&self, id: String) -> Result<JsValue, JsError> {
+    pub fn profile(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.0.profile())?)
+    }
+    pub async fn update_person(&self, person: JsValue) -> Result<(), JsError> {
+        self.0
+            .update_person(serde_wasm_bindgen::from_value(person)?)
+            .await
+            .mje()?;
+        self.flush().await
+    }
+    pub async fn fetch_avatar(
+        &self,
+        id: String,
+        hash: String,
+        size: u64,
+    ) -> Result<Vec<u8>, JsError> {
+        let avatar = self.0.fetch_avatar(id, hash, size).await.mje()?;
+        self.flush().await?;
+        Ok(avatar)
+    }
+    pub async fn request_friend(
+        &self,
+        id: String,
+        greeting: Option<String>,
+    ) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(
+            &self.0.request_friend(id, greeting).await.mje()?,
+        )?)
+    }
+    pub async fn request_chat(&self, id: String) -> Result<JsValue, JsError> {
         Ok(serde_wasm_bindgen::to_value(
-            &self.0.request_person(id).await.mje()?,
+            &self.0.request_chat(id).await.mje()?,
         )?)
     }
-    pub async fn request_friend(&self, id: String) -> Result<bool, JsError> {
-        self.0.request_friend(id).await.mje()
+    pub async fn send_message(&self, chat_handle: usize, payload: Vec<u8>) -> Result<(), JsError> {
+        self.0.send_message(chat_handle, payload).await.mje()
     }
-    pub async fn request_chat(&self, id: String) -> Result<Option<usize>, JsError> {
-        self.0.request_chat(id).await.mje()
+    pub async fn next_message(&self, chat_handle: usize) -> Result<Option<Vec<u8>>, JsError> {
+        self.0.next_message(chat_handle).await.mje()
+    }
+    pub async fn close_chat(&self, chat_handle: usize) {
+        self.0.close_chat(chat_handle).await
+    }
+    pub fn create_group_ticket(
+        &self,
+        group_handle: usize,
+        options: JsValue,
+    ) -> Result<String, JsError> {
+        self.0
+            .create_group_ticket(group_handle, serde_wasm_bindgen::from_value(options)?)
+            .mje()
+    }
+    pub async fn create_group(&self, name: String) -> Result<usize, JsError> {
+        self.0.create_group(name).await.mje()
     }
     pub async fn subscribe_group(&self, ticket: String) -> Result<usize, JsError> {
         self.0.subscribe_group(ticket).await.mje()
     }
+    pub async fn invite_to_group(
+        &self,
+        friend_id: String,
+        group_handle: usize,
+    ) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(
+            &self
+                .0
+                .invite_to_group(friend_id, group_handle)
+                .await
+                .mje()?,
+        )?)
+    }
+    pub async fn group_broadcast(
+        &self,
+        group_handle: usize,
+        payload: Vec<u8>,
+    ) -> Result<(), JsError> {
+        self.0.group_broadcast(group_handle, payload).await.mje()
+    }
+    pub async fn group_next_event(&self, group_handle: usize) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(
+            &self.0.group_next_event(group_handle).await.mje()?,
+        )?)
+    }
+    pub fn group_membership(&self, group_handle: usize) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(
+            &self.0.group_membership(group_handle).mje()?,
+        )?)
+    }
+    pub async fn manage_group(&self, group_handle: usize, action: JsValue) -> Result<(), JsError> {
+        self.0
+            .manage_group(group_handle, serde_wasm_bindgen::from_value(action)?)
+            .await
+            .mje()
+    }
+    pub fn group_neighbors(&self, group_handle: usize) -> Result<Vec<String>, JsError> {
+        self.0.group_neighbors(group_handle).mje()
+    }
+    pub fn leave_group(&self, group_handle: usize) {
+        self.0.leave_group(group_handle)
+    }
+    pub async fn block(&self, id: String) -> Result<(), JsError> {
+        self.0.block(id).await.mje()
+    }
+    pub async fn unblock(&self, id: String) -> Result<(), JsError> {
+        self.0.unblock(id).await.mje()
+    }
+    pub fn list_blocked(&self) -> Vec<String> {
+        self.0.list_blocked()
+    }
+    pub async fn allow(&self, id: String) -> Result<(), JsError> {
+        self.0.allow(id).await.mje()
+    }
+    pub async fn disallow(&self, id: String) -> Result<(), JsError> {
+        self.0.disallow(id).await.mje()
+    }
+    pub fn list_allowed(&self) -> Vec<String> {
+        self.0.list_allowed()
+    }
+    pub fn allow_only(&self) -> bool {
+        self.0.allow_only()
+    }
+    pub async fn set_allow_only(&self, allow_only: bool) -> Result<(), JsError> {
+        self.0.set_allow_only(allow_only).await.mje()
+    }
+    pub fn protocol_metrics(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.0.protocol_metrics())?)
+    }
+    pub async fn unfriend(&self, id: String) -> Result<(), JsError> {
+        self.0.unfriend(id).await.mje()
+    }
+    pub async fn friend_status(&self, id: String) -> Result<bool, JsError> {
+        self.0.friend_status(id).await.mje()
+    }
+    pub async fn add_friend(&self, id: String) -> Result<(), JsError> {
+        self.0.add_friend(id).await.mje()
+    }
+    pub fn list_friends(&self) -> Vec<String> {
+        self.0.list_friends()
+    }
+    pub async fn share_bytes(&self, bytes: Vec<u8>) -> Result<JsValue, JsError> {
+        let shared = self.0.share_bytes(bytes).await.mje()?;
+        self.flush().await?;
+        Ok(serde_wasm_bindgen::to_value(&shared)?)
+    }
+    pub fn download(&self, id: String, hash: String, callback: Function) -> Result<usize, JsError> {
+        let transfer_handle = self.0.download(id, hash, None).mje()?;
+        forward_transfer(self.0.clone(), self.1.clone(), transfer_handle, callback);
+        Ok(transfer_handle)
+    }
+    pub async fn list_collection(&self, id: String, hash: String) -> Result<JsValue, JsError> {
+        let collection = self.0.list_collection(id, hash).await.mje()?;
+        self.flush().await?;
+        Ok(serde_wasm_bindgen::to_value(&collection)?)
+    }
+    pub async fn download_collection(
+        &self,
+        id: String,
+        hash: String,
+        entries: Option<Vec<usize>>,
+        callback: Function,
+    ) -> Result<usize, JsError> {
+        let transfer_handle = self
+            .0
+            .download_collection(id, hash, entries, None)
+            .await
+            .mje()?;
+        forward_transfer(self.0.clone(), self.1.clone(), transfer_handle, callback);
+        Ok(transfer_handle)
+    }
+    pub fn cancel_transfer(&self, transfer_handle: usize) {
+        self.0.cancel_transfer(transfer_handle)
+    }
+    pub async fn read_blob(&self, hash: String) -> Result<Vec<u8>, JsError> {
+        self.0.read_blob(hash).await.mje()
+    }
+    pub async fn tag_blob(&self, hash: String, owner: JsValue) -> Result<(), JsError> {
+        self.0
+            .tag_blob(hash, serde_wasm_bindgen::from_value(owner)?)
+            .await
+            .mje()?;
+        self.flush().await
+    }
+    pub async fn untag_blob(&self, hash: String, owner: JsValue) -> Result<(), JsError> {
+        self.0
+            .untag_blob(hash, serde_wasm_bindgen::from_value(owner)?)
+            .await
+            .mje()?;
+        self.flush().await
+    }
+    pub async fn delete_blob(&self, hash: String) -> Result<(), JsError> {
+        self.0.delete_blob(hash).await.mje()?;
+        self.flush().await
+    }
+    pub async fn clear_cache(&self) -> Result<(), JsError> {
+        self.0.clear_cache().await.mje()?;
+        self.flush().await
+    }
+    pub async fn storage_usage(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(
+            &self.0.storage_usage().await.mje()?,
+        )?)
+    }
+    pub async fn set_storage_quota(&self, quota: Option<u64>) -> Result<(), JsError> {
+        self.0.set_storage_quota(quota).await.mje()?;
+        self.flush().await
+    }
+    pub fn privacy(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.0.privacy())?)
+    }
+    pub async fn set_privacy(&self, privacy: JsValue) -> Result<(), JsError> {
+        self.0
+            .set_privacy(serde_wasm_bindgen::from_value(privacy)?)
+            .await
+            .mje()
+    }
+}
+impl Endpoint {
+    async fn flush(&self) -> Result<(), JsError> {
+        if let Some(blob_sync) = &self.1 {
+            blob_sync.flush().await.mje()?;
+        }
+        Ok(())
+    }
+}
+
+fn forward_transfer(
+    endpoint: endpoint::Endpoint,
+    blob_sync: Option<Rc<BlobSync>>,
+    transfer_handle: usize,
+    callback: Function,
+) {
+    spawn_local(async move {
+        loop {
+            match async {
+                let Some(event) = endpoint.transfer_next_event(transfer_handle).await? else {
+                    return eyre::Ok(false);
+                };
+                callback
+                    .call1(
+                        &JsValue::NULL,
+                        &serde_wasm_bindgen::to_value(&event)
+                            .map_err(|err| eyre!(err.to_string()))?,
+                    )
+                    .map_err(|err| eyre!("{:?}", err))?;
+                eyre::Ok(true)
+            }
+            .await
+            {
+                Ok(true) => (),
+                Ok(false) => {
+                    if let Some(blob_sync) = &blob_sync
+                        && let Err(err) = blob_sync.flush().await
+                    {
+                        log::error!("{}", err);
+                    }
+                    break;
+                }
+                Err(err) => {
+                    log::error!("{}", err);
+                    break;
+                }
+            }
+        }
+    });
 }
 
 #[wasm_bindgen]
@@ -62,10 +389,8 @@ pub fn get_secret_key_id(secret_key: Vec<u8>) -> Result<String, JsError> {
     endpoint::get_secret_key_id(secret_key).mje()
 }
 #[wasm_bindgen]
-pub fn generate_group_id() -> String {
-    endpoint::generate_group_id()
-}
-#[wasm_bindgen]
-pub fn generate_ticket(group_id: String, bootstrap: Vec<String>) -> Result<String, JsError> {
-    endpoint::generate_ticket(group_id, bootstrap).mje()
+pub fn parse_ticket(ticket: String) -> Result<JsValue, JsError> {
+    Ok(serde_wasm_bindgen::to_value(
+        &endpoint::parse_ticket(ticket).mje()?,
+    )?)
 }
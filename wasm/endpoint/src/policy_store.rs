@@ -0,0 +1,38 @@
+use eyre::{Result, eyre};
+use n0_future::boxed::BoxFuture;
+use person_protocol::policy::{PolicyList, PolicyStore};
+use web_sys::Storage;
+
+#[derive(Debug)]
+pub struct LocalStoragePolicyStore(String);
+impl LocalStoragePolicyStore {
+    pub fn new(key: String) -> Self {
+        Self(key)
+    }
+    fn storage() -> Result<Storage> {
+        web_sys::window()
+            .ok_or_else(|| eyre!("无法获取window"))?
+            .local_storage()
+            .map_err(|err| eyre!("{:?}", err))?
+            .ok_or_else(|| eyre!("无法获取localStorage"))
+    }
+}
+impl PolicyStore for LocalStoragePolicyStore {
+    fn load(&self) -> Result<PolicyList> {
+        match Self::storage()?
+            .get_item(&self.0)
+            .map_err(|err| eyre!("{:?}", err))?
+        {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(Default::default()),
+        }
+    }
+    fn save(&self, list: PolicyList) -> BoxFuture<Result<()>> {
+        let key = self.0.clone();
+        Box::pin(async move {
+            Self::storage()?
+                .set_item(&key, &serde_json::to_string(&list)?)
+                .map_err(|err| eyre!("{:?}", err))
+        })
+    }
+}